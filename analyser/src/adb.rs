@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 
+use serde::Serialize;
+
 use crate::patches::Patcher;
 
 pub struct AdbIndexEntry {
@@ -34,14 +36,14 @@ pub struct AdbEntry {
     pub xrefs: Vec<AdbXref>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct AdbXref {
     pub other_key: String,
     pub loc: Option<usize>,
     pub kind: AdbXrefKind,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub enum AdbXrefPathKind {
     Animation,
     Character,
@@ -53,7 +55,7 @@ pub enum AdbXrefPathKind {
 }
 
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub enum AdbXrefRegionKind {
     ScreenPos,
     ScreenRegion,
@@ -62,7 +64,7 @@ pub enum AdbXrefRegionKind {
     Other,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub enum AdbXrefTextKind {
     Dialogue,
     DisplayName,
@@ -71,7 +73,7 @@ pub enum AdbXrefTextKind {
     Other,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub enum AdbXrefKind {
     DialogueText,
     Scene,
@@ -167,28 +169,72 @@ impl AdbEntry {
     }
 }
 
+/// Applies the patches to a .adb file in place, so that everything which is
+/// not patched keeps its bytes and order. An object which grows is moved to
+/// the end of the file; only the index rows of resized objects are rewritten.
 pub fn create_patched(mut db: Vec<u8>, patcher: Patcher) -> Vec<u8> {
     let count = u32::from_le_bytes(db[8..12].try_into().unwrap()) as usize;
+    let data_start = 0x14 + 0x28 * count;
     for i in 0..count {
-        let (idx, key, size) = {
-            let pos = 0x14 + 0x28 * i;
-            let idx = u32::from_le_bytes(db[pos..pos + 4].try_into().unwrap()) as usize;
-            let nullbyte = db[pos + 4..pos + 36].iter().position(|b| *b == 0).unwrap_or(32) + 4;
-            let key = std::str::from_utf8(&db[pos + 4..pos + nullbyte]).unwrap().to_string();
-            let size = u32::from_le_bytes(db[pos + 36..pos + 40].try_into().unwrap()) as usize;
-            (idx, key, size)
-        };
+        let row = 0x14 + 0x28 * i;
+        let idx = u32::from_le_bytes(db[row..row + 4].try_into().unwrap()) as usize;
+        let nullbyte = db[row + 4..row + 36].iter().position(|b| *b == 0).unwrap_or(32) + 4;
+        let key = std::str::from_utf8(&db[row + 4..row + nullbyte]).unwrap().to_string();
+        let size = u32::from_le_bytes(db[row + 36..row + 40].try_into().unwrap()) as usize;
         if size == 0 {
             unreachable!("empty entry");
         }
-        let pos = 0x14 + 0x28 * count + idx;
-        let entry = db[pos..pos + size].to_vec();
-        patcher.with_data(&key, &entry, |patched, patches| {
-            if !patches.is_empty() {
-                println!("  patching {key}");
-                db[pos..pos + size].copy_from_slice(patched);
-            }
+        let pos = data_start + idx;
+        let patched = patcher.with_data(&key, &db[pos..pos + size], |patched, patches| {
+            (!patches.is_empty()).then(|| patched.to_vec())
         });
+        let Some(patched) = patched else { continue; };
+        println!("  patching {key}");
+        let mut pos = pos;
+        if patched.len() <= size {
+            db[pos..pos + patched.len()].copy_from_slice(&patched);
+        } else {
+            pos = db.len();
+            db.extend_from_slice(&patched);
+        }
+        if patched.len() != size {
+            db[row..row + 4].copy_from_slice(&((pos - data_start) as u32).to_le_bytes());
+            db[row + 36..row + 40].copy_from_slice(&(patched.len() as u32).to_le_bytes());
+        }
+    }
+    db
+}
+
+/// Serialises objects into a new .adb file. Objects are written in the given
+/// order. Objects which carry no data (dummies, globals, scenes) are skipped.
+pub fn create<'a>(entries: impl IntoIterator<Item = (&'a str, &'a AdbEntry)>) -> Vec<u8> {
+    let entries = entries.into_iter()
+        .filter(|(_, entry)| entry.size() > 0)
+        .collect::<Vec<_>>();
+    let count = entries.len() as u32;
+    let mut db = Vec::with_capacity(0x14 + 0x28 * entries.len() + entries.iter().map(|(_, e)| e.size()).sum::<usize>());
+    db.extend_from_slice(b"\x9A\x02\x00\x00");
+    db.extend_from_slice(b"\x00\x00\x00\x00");
+    db.extend_from_slice(&count.to_le_bytes());
+    db.extend_from_slice(&count.to_le_bytes());
+    db.extend_from_slice(b"\x1F\x00\x00\x00");
+
+    // index table
+    let mut idx = 0;
+    for (key, entry) in &entries {
+        assert!(key.len() <= 32, "key too long: {key}");
+        assert!(!key.contains('\0'), "key contains null byte: {key}");
+        let mut key_buf = [0; 32];
+        key_buf[..key.len()].copy_from_slice(key.as_bytes());
+        db.extend_from_slice(&(idx as u32).to_le_bytes());
+        db.extend_from_slice(&key_buf);
+        db.extend_from_slice(&(entry.size() as u32).to_le_bytes());
+        idx += entry.size();
+    }
+
+    // data
+    for (_, entry) in &entries {
+        db.extend_from_slice(entry.raw());
     }
     db
 }
@@ -0,0 +1,582 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    dis::{code::{opcodes::DisIns, opmap::OpcodeMap, DisOp}, DisError},
+    encoding::TextEncoding,
+    version::VersionProfile,
+};
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum AsmError {
+    MalformedLine(usize, String),
+    UnknownMnemonic(usize, String),
+    InvalidOperand(usize, String),
+    UnknownLabel(usize, String),
+    DuplicateLabel(usize, String),
+    JumpOutOfRange(usize, String),
+    InvalidString(String),
+    TooLarge(String),
+    InvalidTemplate,
+}
+
+/// Assembled code section.
+pub struct AsmOutput {
+    /// Bytecode, as it should appear at offset 0x18 of a code object.
+    pub code: Vec<u8>,
+
+    /// String pool. String operands are encoded as indices into this list.
+    pub strings: Vec<String>,
+
+    /// Label offsets, relative to the start of the code section.
+    pub labels: HashMap<String, usize>,
+}
+
+impl AsmOutput {
+    /// Builds a complete code object: header, code section, and string pool.
+    /// The object size, magic, code size and string count are worked out.
+    /// The other header fields and the string pool metadata are not
+    /// understood yet: the header is copied from `template` (an existing code
+    /// object), and so is the metadata if the template has as many strings.
+    /// Otherwise, the unknown header bytes are set to `01 05` and
+    /// `01 00 00 00` around the magic, as in most objects, and the metadata
+    /// is zeroed, which the game may not accept. Strings are written in the
+    /// given encoding.
+    pub fn build_object(&self, template: Option<&[u8]>, encoding: TextEncoding) -> Result<Vec<u8>, AsmError> {
+        let code_size = self.code.len();
+        let string_count = self.strings.len();
+        if code_size > 0xFFFF {
+            return Err(AsmError::TooLarge(format!("code section of {code_size} bytes")));
+        }
+        if string_count > 0xFFFF {
+            return Err(AsmError::TooLarge(format!("{string_count} strings")));
+        }
+
+        // header
+        let mut object = match template {
+            Some(template) => {
+                if template.get(8..12) != Some(b"\xAD\xDE\x0C\x00") || template.len() < 0x18 {
+                    return Err(AsmError::InvalidTemplate);
+                }
+                template[0..0x18].to_vec()
+            }
+            None => {
+                let mut header = vec![0; 0x18];
+                header[6..16].copy_from_slice(b"\x01\x05\xAD\xDE\x0C\x00\x01\x00\x00\x00");
+                header
+            }
+        };
+        object[0x12..0x14].copy_from_slice(&(code_size as u16).to_le_bytes());
+        object[0x14..0x16].copy_from_slice(&(string_count as u16).to_le_bytes());
+
+        // code
+        object.extend_from_slice(&self.code);
+
+        // string pool
+        let string_pool_meta = 5 + 4 * string_count;
+        let template_meta = template.and_then(|template| {
+            let template_code_size = u16::from_le_bytes(template[0x12..0x14].try_into().unwrap()) as usize;
+            let template_string_count = u16::from_le_bytes(template[0x14..0x16].try_into().unwrap()) as usize;
+            let start = 0x18 + template_code_size;
+            (template_string_count == string_count)
+                .then(|| template.get(start..start + string_pool_meta))
+                .flatten()
+        });
+        match template_meta {
+            Some(meta) => object.extend_from_slice(meta),
+            None => object.resize(object.len() + string_pool_meta, 0),
+        }
+        for s in &self.strings {
+            let encoded = encoding.encode(s)
+                .filter(|encoded| !encoded.contains(&0))
+                .ok_or_else(|| AsmError::InvalidString(s.clone()))?;
+            object.extend_from_slice(&encoded);
+            object.push(0);
+        }
+
+        let size = object.len() - 7;
+        if size > 0xFFFF {
+            return Err(AsmError::TooLarge(format!("code object of {size} bytes")));
+        }
+        object[4..6].copy_from_slice(&(size as u16).to_le_bytes());
+        Ok(object)
+    }
+}
+
+enum AsmOperand {
+    None,
+    Int(i64),
+    Str(String),
+    Label(String),
+}
+
+enum AsmItem {
+    Ins {
+        line: usize,
+        op: DisOp,
+        operand: AsmOperand,
+    },
+    Bytes(Vec<u8>),
+    String(String),
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Removes a `;` comment from the line, ignoring semicolons in strings.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..idx],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn parse_string(line: usize, s: &str) -> Result<String, AsmError> {
+    let Some(inner) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) else {
+        return Err(AsmError::InvalidOperand(line, s.to_string()));
+    };
+    let mut ret = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Err(AsmError::InvalidOperand(line, s.to_string())),
+            '\\' => ret.push(match chars.next() {
+                Some('\\') => '\\',
+                Some('"') => '"',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                _ => return Err(AsmError::InvalidOperand(line, s.to_string())),
+            }),
+            c => ret.push(c),
+        }
+    }
+    Ok(ret)
+}
+
+fn parse_int(line: usize, s: &str) -> Result<i64, AsmError> {
+    let (neg, abs) = match s.strip_prefix('-') {
+        Some(abs) => (true, abs),
+        None => (false, s),
+    };
+    let value = match abs.strip_prefix("0x").or_else(|| abs.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => abs.parse::<i64>(),
+    }.map_err(|_| AsmError::InvalidOperand(line, s.to_string()))?;
+    Ok(if neg { -value } else { value })
+}
+
+fn parse_operand(line: usize, s: &str) -> Result<AsmOperand, AsmError> {
+    if s.is_empty() {
+        Ok(AsmOperand::None)
+    } else if s.starts_with('"') {
+        Ok(AsmOperand::Str(parse_string(line, s)?))
+    } else if s.starts_with('-') || s.starts_with(|c: char| c.is_ascii_digit()) {
+        Ok(AsmOperand::Int(parse_int(line, s)?))
+    } else if is_ident(s) {
+        Ok(AsmOperand::Label(s.to_string()))
+    } else {
+        Err(AsmError::InvalidOperand(line, s.to_string()))
+    }
+}
+
+fn find_op(mnemonic: &str) -> Option<DisOp> {
+    (0..256)
+        .find(|idx| !DisOp::NAME[*idx].is_empty() && DisOp::NAME[*idx].eq_ignore_ascii_case(mnemonic))
+        .and_then(|idx| DisOp::VARIANTS[idx])
+}
+
+/// Encodes `value` in `size` bytes, little-endian. Both signed and unsigned
+/// values are accepted, as long as they fit.
+fn encode_imm(line: usize, value: i64, size: usize, out: &mut Vec<u8>) -> Result<(), AsmError> {
+    let bits = 8 * size as u32;
+    if size == 0 || value < -(1 << (bits - 1)) || value >= (1 << bits) {
+        return Err(AsmError::InvalidOperand(line, format!("{value} does not fit in {size} bytes")));
+    }
+    out.extend_from_slice(&(value as u64).to_le_bytes()[0..size]);
+    Ok(())
+}
+
+/// Assembles a code section from text. Each line contains at most one
+/// instruction, using the mnemonics of the disassembler, optionally preceded
+/// by a label (`name:`). Comments start with `;`. Operands can be:
+///
+/// - integers (`3`, `-1`, `0x03`);
+/// - string constants (`"1006.1058"`), which are added to the string pool if
+///   not already present, and are encoded as their index in the pool;
+/// - labels, for jump instructions.
+///
+/// There are also two directives: `.string "..."` adds a string to the pool
+/// (even if an identical string is already in it), `.byte 0x12 0x34 ...`
+/// emits raw bytes. Opcodes are encoded with the given opcode map.
+pub fn assemble(source: &str, map: &OpcodeMap) -> Result<AsmOutput, AsmError> {
+    // First pass: parse lines, find label positions.
+    let mut items = Vec::new();
+    let mut labels = HashMap::new();
+    let mut pos = 0;
+    for (line_idx, line) in source.lines().enumerate() {
+        let line_no = line_idx + 1;
+        let mut line = strip_comment(line).trim();
+        while let Some((label, rest)) = line.split_once(':')
+            && is_ident(label.trim()) {
+            if labels.insert(label.trim().to_string(), pos).is_some() {
+                return Err(AsmError::DuplicateLabel(line_no, label.trim().to_string()));
+            }
+            line = rest.trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let (mnemonic, operand) = match line.split_once(char::is_whitespace) {
+            Some((mnemonic, operand)) => (mnemonic, operand.trim()),
+            None => (line, ""),
+        };
+        match mnemonic {
+            ".byte" => {
+                let bytes = operand.split_whitespace()
+                    .map(|b| {
+                        let value = parse_int(line_no, b)?;
+                        u8::try_from(value).map_err(|_| AsmError::InvalidOperand(line_no, b.to_string()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                pos += bytes.len();
+                items.push(AsmItem::Bytes(bytes));
+            }
+            ".string" => items.push(AsmItem::String(parse_string(line_no, operand)?)),
+            _ if mnemonic.starts_with('.') => return Err(AsmError::MalformedLine(line_no, line.to_string())),
+            _ => {
+                let op = find_op(mnemonic).ok_or_else(|| AsmError::UnknownMnemonic(line_no, mnemonic.to_string()))?;
+                pos += 1 + DisOp::IMM_SIZE[op as usize];
+                items.push(AsmItem::Ins {
+                    line: line_no,
+                    op,
+                    operand: parse_operand(line_no, operand)?,
+                });
+            }
+        }
+    }
+
+    // Second pass: encode instructions.
+    let mut code = Vec::new();
+    let mut strings: Vec<String> = Vec::new();
+    for item in items {
+        let (line, op, operand) = match item {
+            AsmItem::Ins { line, op, operand } => (line, op, operand),
+            AsmItem::Bytes(bytes) => {
+                code.extend(bytes);
+                continue;
+            }
+            AsmItem::String(s) => {
+                strings.push(s);
+                continue;
+            }
+        };
+        let pos = code.len();
+        let imm_size = DisOp::IMM_SIZE[op as usize];
+        code.push(map.byte_of(op));
+        match operand {
+            AsmOperand::None if imm_size == 0 => (),
+            AsmOperand::None => return Err(AsmError::InvalidOperand(line, format!("{op:?} requires an operand"))),
+            _ if imm_size == 0 => return Err(AsmError::InvalidOperand(line, format!("{op:?} takes no operand"))),
+            AsmOperand::Int(value) => encode_imm(line, value, imm_size, &mut code)?,
+            AsmOperand::Str(s) => {
+                let idx = match strings.iter().position(|other| *other == s) {
+                    Some(idx) => idx,
+                    None => {
+                        strings.push(s);
+                        strings.len() - 1
+                    }
+                };
+                if imm_size < 4 && idx >= 1 << (8 * imm_size) {
+                    return Err(AsmError::InvalidOperand(line, format!("string index {idx} does not fit in {imm_size} bytes")));
+                }
+                encode_imm(line, idx as i64, imm_size, &mut code)?;
+            }
+            AsmOperand::Label(label) => {
+                if !op.is_jump() {
+                    return Err(AsmError::InvalidOperand(line, format!("{op:?} does not take a label")));
+                }
+                let target = *labels.get(&label).ok_or_else(|| AsmError::UnknownLabel(line, label.clone()))?;
+                // Jump offsets are relative to the instruction, plus 3.
+                let offset = target as i64 - pos as i64 - 3;
+                if matches!(op, DisOp::Jmp32) {
+                    // Only the upper 16 bits of the immediate are used.
+                    if offset < 0 || offset > 0xFFFF {
+                        return Err(AsmError::JumpOutOfRange(line, label));
+                    }
+                    encode_imm(line, offset << 16, imm_size, &mut code)?;
+                } else {
+                    if offset < i16::MIN as i64 || offset > i16::MAX as i64 {
+                        return Err(AsmError::JumpOutOfRange(line, label));
+                    }
+                    encode_imm(line, offset, imm_size, &mut code)?;
+                }
+            }
+        }
+    }
+
+    Ok(AsmOutput {
+        code,
+        strings,
+        labels,
+    })
+}
+
+pub fn escape_string(s: &str) -> String {
+    s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t")
+}
+
+/// Checks the header of a code object, and returns its code section.
+pub fn code_section(object: &[u8]) -> Result<&[u8], DisError> {
+    if object.len() < 0x18 {
+        return Err(DisError::TooShort);
+    }
+    if &object[8..12] != b"\xAD\xDE\x0C\x00" {
+        return Err(DisError::MagicMismatch);
+    }
+    let code_size = u16::from_le_bytes(object[0x12..0x14].try_into().unwrap()) as usize;
+    if 0x18 + code_size > object.len() {
+        return Err(DisError::TooShort);
+    }
+    Ok(&object[0x18..0x18 + code_size])
+}
+
+/// Decodes a code section with a linear sweep. Bytes which cannot be decoded
+/// are returned one at a time, as `None`, and decoding resumes at the next
+/// byte.
+pub fn sweep(code: &[u8], map: &OpcodeMap) -> Vec<(usize, Option<DisIns>)> {
+    let mut instructions = Vec::new();
+    let mut pos = 0;
+    while pos < code.len() {
+        match DisIns::analyse_one(code, pos, map) {
+            Ok((next, ins)) => {
+                instructions.push((pos, Some(ins)));
+                pos = next;
+            }
+            Err(_) => {
+                instructions.push((pos, None));
+                pos += 1;
+            }
+        }
+    }
+    instructions
+}
+
+/// Disassembled code object.
+pub struct DisasmOutput {
+    /// Source, in the format accepted by `assemble`.
+    pub source: String,
+
+    /// Number of bytes in the code section which could not be decoded as
+    /// instructions, and were emitted as `.byte` directives instead.
+    pub undecoded: usize,
+}
+
+/// Disassembles a code object into the text format accepted by `assemble`.
+/// Unlike the analysis in `dis::analyse_code`, this is a linear sweep over the
+/// whole code section, so unreachable code is included as well. Jump targets
+/// which are at an instruction boundary are replaced with labels, named after
+/// their offset in the object.
+pub fn disassemble(object: &[u8], profile: &VersionProfile) -> Result<DisasmOutput, DisError> {
+    let code = code_section(object)?;
+    let string_count = u16::from_le_bytes(object[0x14..0x16].try_into().unwrap()) as usize;
+    let string_pool_start = 0x18 + code.len();
+    if string_pool_start + 5 + 4 * string_count > object.len() {
+        return Err(DisError::TooShort);
+    }
+
+    let mut source = String::new();
+
+    // string pool
+    let mut pos = string_pool_start + 5 + 4 * string_count;
+    for string_idx in 0..string_count {
+        let Some(len) = object[pos..].iter().position(|b| *b == 0) else {
+            return Err(DisError::MalformedString);
+        };
+        let s = profile.encoding.decode(&object[pos..pos + len]);
+        source.push_str(&format!(".string \"{}\" ; #{string_idx} / 0x{string_idx:02x}\n", escape_string(&s)));
+        pos += len + 1;
+    }
+
+    // code
+    let instructions = sweep(code, &profile.map);
+    let starts = instructions.iter()
+        .filter(|(_, ins)| ins.is_some())
+        .map(|(pos, _)| *pos as isize)
+        .chain([code.len() as isize])
+        .collect::<HashSet<_>>();
+    let label_target = |pos: usize, ins: &DisIns| {
+        let target = ins.jump_target(pos)?;
+        if !starts.contains(&target) {
+            return None;
+        }
+        // The assembler cannot encode the lower 16 bits of a `Jmp32`.
+        if matches!(ins.op, DisOp::Jmp32) && ins.imm_value() & 0xFFFF != 0 {
+            return None;
+        }
+        Some(target as usize)
+    };
+    let labels = instructions.iter()
+        .filter_map(|(pos, ins)| label_target(*pos, ins.as_ref()?))
+        .collect::<HashSet<_>>();
+    let mut undecoded = 0;
+    let mut raw = Vec::new();
+    for (pos, ins) in instructions.iter().map(|(pos, ins)| (*pos, ins)).chain([(code.len(), &None)]) {
+        if !raw.is_empty() && (ins.is_some() || labels.contains(&pos) || pos == code.len()) {
+            source.push_str(&format!("    .byte {}\n", raw.iter().map(|b| format!("0x{b:02x}")).collect::<Vec<_>>().join(" ")));
+            undecoded += raw.len();
+            raw.clear();
+        }
+        if labels.contains(&pos) {
+            source.push_str(&format!("l{:04x}:\n", 0x18 + pos));
+        }
+        match ins {
+            Some(ins) => match label_target(pos, ins) {
+                Some(target) => source.push_str(&format!("    {} l{:04x}\n", DisOp::NAME[ins.op_byte as usize], 0x18 + target)),
+                None => source.push_str(&format!("    {}\n", ins.to_string().trim_end())),
+            },
+            None if pos < code.len() => raw.push(code[pos]),
+            None => (),
+        }
+    }
+
+    Ok(DisasmOutput {
+        source,
+        undecoded,
+    })
+}
+
+pub enum RoundtripResult {
+    /// Reassembled object is identical to the original, in the parts which
+    /// are checked (see `verify_roundtrip`).
+    Identical {
+        undecoded: usize,
+    },
+
+    /// Reassembled object differs; first differing offset.
+    Mismatch {
+        offset: usize,
+    },
+
+    /// Object could not be disassembled or reassembled.
+    Failed(String),
+}
+
+/// Disassembles a code object, reassembles it, and compares the result to the
+/// original. Only the code section, the strings and the size fields are
+/// checked: the other header fields and the string pool metadata are not
+/// understood yet, and are copied from the original object by
+/// `build_object`.
+pub fn verify_roundtrip(object: &[u8], profile: &VersionProfile) -> (RoundtripResult, Option<String>) {
+    let disassembled = match disassemble(object, profile) {
+        Ok(disassembled) => disassembled,
+        Err(err) => return (RoundtripResult::Failed(format!("cannot disassemble: {err:?}")), None),
+    };
+    let reassembled = assemble(&disassembled.source, &profile.map)
+        .and_then(|assembled| assembled.build_object(Some(object), profile.encoding));
+    let reassembled = match reassembled {
+        Ok(reassembled) => reassembled,
+        Err(err) => return (RoundtripResult::Failed(format!("cannot reassemble: {err:?}")), Some(disassembled.source)),
+    };
+    let result = match object.iter().zip(reassembled.iter()).position(|(a, b)| a != b) {
+        Some(offset) => RoundtripResult::Mismatch { offset },
+        None if object.len() != reassembled.len() => RoundtripResult::Mismatch { offset: object.len().min(reassembled.len()) },
+        None => RoundtripResult::Identical { undecoded: disassembled.undecoded },
+    };
+    (result, Some(disassembled.source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+.string \"unused\"
+start:
+    PushImm8a 2
+    PushImm16a 0x1234
+    PushImm32 -1
+    Add
+    Jez end
+    PushImm8b \"1006.1058\"
+    GlbGet 3
+    Pop
+    Jmp start
+    .byte 0xff
+end:
+    Jmp32 0x10000
+    Exit
+";
+
+    fn object(version: &str) -> (VersionProfile, Vec<u8>) {
+        let profile = VersionProfile::load(version).unwrap();
+        let object = assemble(SOURCE, &profile.map).unwrap().build_object(None, profile.encoding).unwrap();
+        (profile, object)
+    }
+
+    /// Builds every byte of the object from the disassembly, without a
+    /// template, so that the header and string pool are checked too.
+    #[test]
+    fn roundtrip_whole_object() {
+        for version in OpcodeMap::builtin_names() {
+            let (profile, object) = object(version);
+            let disassembled = disassemble(&object, &profile).unwrap();
+            assert_eq!(disassembled.undecoded, 1, "{version}");
+            let reassembled = assemble(&disassembled.source, &profile.map).unwrap().build_object(None, profile.encoding).unwrap();
+            assert_eq!(object, reassembled, "{version}");
+        }
+    }
+
+    #[test]
+    fn roundtrip_encodes_strings() {
+        let profile = VersionProfile::load("1.0en").unwrap();
+        let source = ".string \"Černá růže\"\n    Exit\n";
+        let object = assemble(source, &profile.map).unwrap().build_object(None, profile.encoding).unwrap();
+        assert!(object.ends_with(b"\xC8ern\xE1 r\xF9\x9Ee\x00"));
+        let disassembled = disassemble(&object, &profile).unwrap();
+        assert!(disassembled.source.contains("\"Černá růže\""));
+    }
+
+    #[test]
+    fn unencodable_strings_are_errors() {
+        let profile = VersionProfile::load("1.0en").unwrap();
+        for source in [".string \"Чёрная роза\"\n", ".string \"a\0b\"\n"] {
+            let result = assemble(source, &profile.map).unwrap().build_object(None, profile.encoding);
+            assert!(matches!(result, Err(AsmError::InvalidString(_))), "{source}");
+        }
+    }
+
+    #[test]
+    fn verify_roundtrip_reports_identical() {
+        let (profile, object) = object("1.0pl");
+        let (result, _) = verify_roundtrip(&object, &profile);
+        assert!(matches!(result, RoundtripResult::Identical { undecoded: 1 }));
+    }
+
+    #[test]
+    fn verify_roundtrip_reports_mismatch() {
+        let (profile, mut object) = object("1.0en");
+        // A null byte in the last string cuts it short, and what follows it
+        // is lost when reassembling.
+        let last = object.len() - 2;
+        object[last] = 0;
+        let (result, _) = verify_roundtrip(&object, &profile);
+        assert!(!matches!(result, RoundtripResult::Identical { .. }));
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{asm, dis::{code::{opdb::OpcodeDb, opmap::OpcodeMap, DisOp}, DisError}};
+
+/// Counts of the values seen for one statistic, most common first when
+/// reported.
+#[derive(Default)]
+struct Histogram<T>(BTreeMap<T, usize>);
+
+impl<T: Ord + Clone> Histogram<T> {
+    fn add(&mut self, value: T) {
+        *self.0.entry(value).or_default() += 1;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Lists the `top` most common values, with the number of other values
+    /// left out.
+    fn show(&self, top: usize, fmt: impl Fn(&T) -> String) -> String {
+        let mut sorted = self.0.iter().collect::<Vec<_>>();
+        sorted.sort_by(|(a_value, a), (b_value, b)| b.cmp(a).then(a_value.cmp(b_value)));
+        let mut ret = sorted.iter()
+            .take(top)
+            .map(|(value, count)| format!("{} ({count})", fmt(value)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if sorted.len() > top {
+            ret.push_str(&format!(", ... (+{} more)", sorted.len() - top));
+        }
+        ret
+    }
+}
+
+/// Uses of one opcode.
+#[derive(Default)]
+struct OpcodeUses {
+    uses: usize,
+    objects: Histogram<String>,
+    imm: Histogram<u32>,
+    /// Values of the stack operands, in the order they are pushed, when they
+    /// are pushed by an immediate push right before. `None` for operands which
+    /// are computed.
+    args: Vec<Histogram<Option<u32>>>,
+    before: Histogram<u8>,
+    after: Histogram<u8>,
+}
+
+/// Opcode usage over a set of code objects. Objects are decoded with a linear
+/// sweep, like the disassembler does, so unreachable code is counted as well.
+pub struct OpcodeCensus<'a> {
+    map: &'a OpcodeMap,
+    ops: Vec<OpcodeUses>,
+    objects: usize,
+    instructions: usize,
+    undecoded: usize,
+}
+
+impl<'a> OpcodeCensus<'a> {
+    /// Starts a census of objects encoded with the given opcode map.
+    pub fn new(map: &'a OpcodeMap) -> Self {
+        Self {
+            map,
+            ops: (0..256).map(|_| OpcodeUses::default()).collect(),
+            objects: 0,
+            instructions: 0,
+            undecoded: 0,
+        }
+    }
+
+    pub fn add_object(&mut self, key: &str, object: &[u8]) -> Result<(), DisError> {
+        let code = asm::code_section(object)?;
+        let instructions = asm::sweep(code, self.map);
+        let targets = instructions.iter()
+            .filter_map(|(pos, ins)| ins.as_ref()?.jump_target(*pos))
+            .collect::<HashSet<_>>();
+        self.objects += 1;
+
+        // Immediates pushed onto the stack since the last jump or jump target,
+        // which is all that is known without following the control flow.
+        let mut stack: Vec<Option<u32>> = Vec::new();
+        for (idx, (pos, ins)) in instructions.iter().enumerate() {
+            if targets.contains(&(*pos as isize)) {
+                stack.clear();
+            }
+            let Some(ins) = ins else {
+                self.undecoded += 1;
+                stack.clear();
+                continue;
+            };
+            self.instructions += 1;
+            let op = ins.op_byte as usize;
+            let uses = &mut self.ops[op];
+            uses.uses += 1;
+            uses.objects.add(key.to_string());
+            if DisOp::IMM_SIZE[op] > 0 {
+                uses.imm.add(ins.imm_value());
+            }
+            let stack_in = DisOp::STACK_IN[op];
+            uses.args.resize_with(stack_in, Histogram::default);
+            let args = stack.split_off(stack.len().saturating_sub(stack_in));
+            for (arg, value) in uses.args.iter_mut().rev().zip(args.into_iter().rev().map(Some).chain(std::iter::repeat(None))) {
+                arg.add(value.flatten());
+            }
+            if let Some((_, Some(prev))) = idx.checked_sub(1).map(|idx| &instructions[idx]) {
+                uses.before.add(prev.op_byte);
+            }
+            if let Some((_, Some(next))) = instructions.get(idx + 1) {
+                uses.after.add(next.op_byte);
+            }
+
+            if ins.op.is_jump() {
+                stack.clear();
+            } else if matches!(ins.op, DisOp::PushImm32 | DisOp::PushImm16a | DisOp::PushImm8a | DisOp::PushImm16b | DisOp::PushImm8b) {
+                stack.push(Some(ins.imm_value()));
+            } else {
+                stack.extend(std::iter::repeat(None).take(DisOp::STACK_OUT[op]));
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists the opcodes by number of uses. Only the unknown (`Unk*`) opcodes
+    /// are listed unless `all` is set; `top` limits the values listed for
+    /// every statistic. Names and argument labels are taken from `opcodes`.
+    pub fn report(&self, opcodes: &OpcodeDb, all: bool, top: usize) -> String {
+        let is_unknown = |op: usize| DisOp::NAME[op].starts_with("Unk");
+        let name = |op: &u8| DisOp::NAME[*op as usize].to_string();
+        let unknown_uses = (0..256)
+            .filter(|op| is_unknown(*op))
+            .map(|op| self.ops[op].uses)
+            .sum::<usize>();
+        let mut ret = format!(
+            "{} code objects, {} instructions, {} undecodable bytes\n{unknown_uses} uses of unknown opcodes ({:.1}%)\n",
+            self.objects,
+            self.instructions,
+            self.undecoded,
+            100.0 * unknown_uses as f64 / self.instructions.max(1) as f64,
+        );
+
+        let mut listed = (0..256)
+            .filter(|op| !DisOp::NAME[*op].is_empty() && (all || is_unknown(*op)))
+            .collect::<Vec<_>>();
+        listed.sort_by_key(|op| (std::cmp::Reverse(self.ops[*op].uses), *op));
+        let mut unused = Vec::new();
+        for op in listed {
+            let uses = &self.ops[op];
+            if uses.uses == 0 {
+                unused.push(DisOp::NAME[op]);
+                continue;
+            }
+            ret.push_str(&format!(
+                "\n{} (0x{op:02x}): {} uses in {} objects, immediate: {} bytes, stack: {} -> {}\n",
+                DisOp::NAME[op],
+                uses.uses,
+                uses.objects.0.len(),
+                DisOp::IMM_SIZE[op],
+                DisOp::STACK_IN[op],
+                DisOp::STACK_OUT[op],
+            ));
+            let info = opcodes.get(op);
+            if let Some(name) = info.and_then(|info| info.name.as_deref()) {
+                ret.push_str(&format!("  name:       {name}\n"));
+            }
+            if let Some(description) = info.and_then(|info| info.description.as_deref()) {
+                ret.push_str(&format!("  note:       {description}\n"));
+            }
+            ret.push_str(&format!("  objects:    {}\n", uses.objects.show(top, |key| key.clone())));
+            if !uses.imm.is_empty() {
+                let width = 2 * DisOp::IMM_SIZE[op];
+                ret.push_str(&format!("  immediate:  {}\n", uses.imm.show(top, |imm| format!("0x{imm:0width$x}"))));
+            }
+            for (idx, arg) in uses.args.iter().enumerate() {
+                let label = match info.and_then(|info| info.args.get(idx)) {
+                    Some(label) => format!("{label}:"),
+                    None => format!("argument {}:", idx + 1),
+                };
+                ret.push_str(&format!("  {label:11} {}\n", arg.show(top, |value| match value {
+                    Some(value) => value.to_string(),
+                    None => "computed".to_string(),
+                })));
+            }
+            if !uses.before.is_empty() {
+                ret.push_str(&format!("  before:     {}\n", uses.before.show(top, name)));
+            }
+            if !uses.after.is_empty() {
+                ret.push_str(&format!("  after:      {}\n", uses.after.show(top, name)));
+            }
+        }
+        if !unused.is_empty() {
+            ret.push_str(&format!("\nnever used: {}\n", unused.join(", ")));
+        }
+        ret
+    }
+}
@@ -1,16 +1,28 @@
-use crate::dis::code::show_addr;
+use crate::dis::code::{ir::{write_expr, write_jump, write_stmt, BinOp, Expr, Stmt, Style}, DisJump};
 
 #[derive(PartialEq, Eq, Clone)]
 pub(super) enum AstToken {
-    Line(Option<usize>, String),
+    Line(Option<usize>, Stmt),
     Break,
     Continue,
     Exit(Option<usize>),
     Tick(Option<usize>),
     Loop(Vec<AstToken>),
-    While(Option<usize>, String, Vec<AstToken>),
-    Chain(Vec<(Option<usize>, String, bool, Vec<AstToken>)>),
-    Switch(String, Vec<(Option<usize>, String, Vec<AstToken>)>),
+    While(Option<usize>, Expr, Vec<AstToken>),
+    /// Loop with the condition checked at the tail.
+    DoWhile(Option<usize>, Expr, Vec<AstToken>),
+    /// Counter loop: initialisation, condition, update, body.
+    For(Option<usize>, Stmt, Expr, Stmt, Vec<AstToken>),
+    WaitWhile(Option<usize>, Expr),
+    Chain(Vec<(Option<usize>, DisJump, bool, Vec<AstToken>)>),
+    Switch(Expr, Vec<(Option<usize>, Expr, Vec<AstToken>)>),
+    /// Target of a `goto`, at the given block.
+    Label(usize),
+    /// Jump to a label, either unconditional or taken on the given edge.
+    Goto(Option<usize>, Option<DisJump>, usize),
+    /// Conditional jump whose test always has the given result, and which
+    /// was removed.
+    Folded(Option<usize>, Expr, bool),
 }
 
 #[derive(Default)]
@@ -19,7 +31,15 @@ pub(super) struct AstStack {
     pub(super) parent: Option<Box<AstStack>>,
 }
 
-pub(super) fn make_chain(mut branches: Vec<(Option<usize>, String, bool, Vec<AstToken>)>) -> Vec<AstToken> {
+/// Operands of the equality test of a branch, if it tests one.
+fn eq_operands(kind: &DisJump) -> Option<(&Expr, &Expr)> {
+    match kind {
+        DisJump::Conditional { test: Expr::Binop(BinOp::Eq | BinOp::StrEq, lhs, rhs) } => Some((lhs, rhs)),
+        _ => None,
+    }
+}
+
+pub(super) fn make_chain(mut branches: Vec<(Option<usize>, DisJump, bool, Vec<AstToken>)>) -> Vec<AstToken> {
     assert!(!branches.is_empty());
     if branches.len() == 2
         && !branches[0].2
@@ -32,9 +52,6 @@ pub(super) fn make_chain(mut branches: Vec<(Option<usize>, String, bool, Vec<Ast
         output.extend(rest);
         return output;
     }
-    use once_cell::sync::Lazy;
-    use regex::Regex;
-    static SWITCH_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"if \(\((.*)\) ==s? \((.*)\)\)").unwrap());
     loop {
         match branches.last() {
             Some((_, _, true, last)) if last.len() == 1 => match &last[0] {
@@ -49,27 +66,25 @@ pub(super) fn make_chain(mut branches: Vec<(Option<usize>, String, bool, Vec<Ast
                     let mut lhs_matches = vec![];
                     let mut rhs_matches = vec![];
                     branches[..branches.len() - 1].iter().for_each(|(_, cond, _, _)| {
-                        let Some(captures) = SWITCH_RE.captures(cond) else { return; };
-                        let Some(lhs) = captures.get(1).map(|m| m.as_str()) else { return; };
-                        let Some(rhs) = captures.get(2).map(|m| m.as_str()) else { return; };
+                        let Some((lhs, rhs)) = eq_operands(cond) else { return; };
                         if test == lhs {
-                            lhs_matches.push(rhs.to_string());
+                            lhs_matches.push(rhs.clone());
                         }
                         if test == rhs {
-                            rhs_matches.push(lhs.to_string());
+                            rhs_matches.push(lhs.clone());
                         }
                     });
                     if lhs_matches.len() == branches.len() - 1 {
                         let AstToken::Switch(test, old_cases) = branches.pop().unwrap().3.pop().unwrap() else { unreachable!(); };
-                        let mut cases = branches.into_iter().enumerate()
-                            .map(|(idx, (cline, _, _, nested))| (cline, lhs_matches[idx].clone(), nested))
+                        let mut cases = branches.into_iter().zip(lhs_matches)
+                            .map(|((cline, _, _, nested), value)| (cline, value, nested))
                             .collect::<Vec<_>>();
                         cases.extend(old_cases);
                         return vec![AstToken::Switch(test, cases)];
                     } else if rhs_matches.len() == branches.len() - 1 {
                         let AstToken::Switch(test, old_cases) = branches.pop().unwrap().3.pop().unwrap() else { unreachable!(); };
-                        let mut cases = branches.into_iter().enumerate()
-                            .map(|(idx, (cline, _, _, nested))| (cline, rhs_matches[idx].clone(), nested))
+                        let mut cases = branches.into_iter().zip(rhs_matches)
+                            .map(|((cline, _, _, nested), value)| (cline, value, nested))
                             .collect::<Vec<_>>();
                         cases.extend(old_cases);
                         return vec![AstToken::Switch(test, cases)];
@@ -83,59 +98,177 @@ pub(super) fn make_chain(mut branches: Vec<(Option<usize>, String, bool, Vec<Ast
     }
     if branches.len() >= 2
         && branches.iter().all(|(_, _, fallthrough, _)| !fallthrough)
-        && let Some(first_captures) = SWITCH_RE.captures(&branches[0].1)
-        && let Some(first_lhs) = first_captures.get(1).map(|m| m.as_str())
-        && let Some(first_rhs) = first_captures.get(2).map(|m| m.as_str()) {
-        let mut lhs_matches = vec![first_rhs.to_string()];
-        let mut rhs_matches = vec![first_lhs.to_string()];
+        && let Some((first_lhs, first_rhs)) = eq_operands(&branches[0].1) {
+        let mut lhs_matches = vec![first_rhs.clone()];
+        let mut rhs_matches = vec![first_lhs.clone()];
         branches[1..].iter().for_each(|(_, cond, _, _)| {
-            let Some(captures) = SWITCH_RE.captures(cond) else { return; };
-            let Some(lhs) = captures.get(1).map(|m| m.as_str()) else { return; };
-            let Some(rhs) = captures.get(2).map(|m| m.as_str()) else { return; };
+            let Some((lhs, rhs)) = eq_operands(cond) else { return; };
             if first_lhs == lhs {
-                lhs_matches.push(rhs.to_string());
+                lhs_matches.push(rhs.clone());
             }
             if first_rhs == rhs {
-                rhs_matches.push(lhs.to_string());
+                rhs_matches.push(lhs.clone());
             }
         });
+        let (first_lhs, first_rhs) = (first_lhs.clone(), first_rhs.clone());
         if lhs_matches.len() == branches.len() {
-            return vec![AstToken::Switch(first_lhs.to_string(), branches.into_iter().enumerate().map(|(idx, (cline, _, _, nested))| (cline, lhs_matches[idx].to_string(), nested)).collect())];
+            return vec![AstToken::Switch(first_lhs, branches.into_iter().zip(lhs_matches).map(|((cline, _, _, nested), value)| (cline, value, nested)).collect())];
         } else if rhs_matches.len() == branches.len() {
-            return vec![AstToken::Switch(first_rhs.to_string(), branches.into_iter().enumerate().map(|(idx, (cline, _, _, nested))| (cline, rhs_matches[idx].to_string(), nested)).collect())];
+            return vec![AstToken::Switch(first_rhs, branches.into_iter().zip(rhs_matches).map(|((cline, _, _, nested), value)| (cline, value, nested)).collect())];
         }
     }
     vec![AstToken::Chain(branches)]
 }
 
-pub(super) fn make_loop(mut content: Vec<AstToken>) -> AstToken {
+/// Whether the innermost loop around `ast` is continued from it.
+fn continues(ast: &[AstToken]) -> bool {
+    ast.iter().any(|token| match token {
+        AstToken::Continue => true,
+        AstToken::Chain(branches) => branches.iter().any(|(.., nested)| continues(nested)),
+        AstToken::Switch(_, cases) => cases.iter().any(|(.., nested)| continues(nested)),
+        _ => false,
+    })
+}
+
+/// Condition of a chain which continues the loop if it holds, and leaves the
+/// chain otherwise.
+fn continue_test(branches: &[(Option<usize>, DisJump, bool, Vec<AstToken>)]) -> Option<(Option<usize>, Expr)> {
+    match branches {
+        [(cline, DisJump::Conditional { test }, _, nested)] if nested == &[AstToken::Continue] => Some((*cline, test.clone())),
+        [(cline, DisJump::Conditional { test }, false, taken), (_, _, true, nested)]
+            if taken.is_empty() && nested == &[AstToken::Continue] => {
+            Some((*cline, negate(test)))
+        }
+        _ => None,
+    }
+}
+
+/// Negates a test the same way as `LogicNot` does.
+fn negate(test: &Expr) -> Expr {
+    let negated = Expr::binop(BinOp::Eq, test.clone(), Expr::Const(0));
+    negated.fold_negation().unwrap_or(negated)
+}
+
+/// Event handler found in the structured code.
+pub(super) struct AstHandler<'a> {
+    /// Offset of the handler opcode.
+    pub(super) pos: usize,
+    /// Tests of the enclosing conditionals, all of which hold when the
+    /// handler is set up.
+    pub(super) conditions: Vec<Expr>,
+    pub(super) body: &'a [AstToken],
+}
+
+/// Finds the event handlers in `ast`. `conditions` are the tests of the
+/// enclosing conditionals.
+pub(super) fn find_handlers<'a>(ast: &'a [AstToken], conditions: &mut Vec<Expr>, found: &mut Vec<AstHandler<'a>>) {
+    fn nested<'a>(body: &'a [AstToken], conditions: &mut Vec<Expr>, extra: Vec<Expr>, found: &mut Vec<AstHandler<'a>>) {
+        let len = conditions.len();
+        conditions.extend(extra);
+        find_handlers(body, conditions, found);
+        conditions.truncate(len);
+    }
+    for token in ast {
+        match token {
+            AstToken::Chain(branches) => {
+                let mut negated = Vec::new();
+                for (cline, kind, _, body) in branches {
+                    let mut extra = negated.clone();
+                    match kind {
+                        DisJump::Conditional { test } => {
+                            extra.push(test.clone());
+                            negated.push(negate(test));
+                        }
+                        // The unknown handler opcodes jump over their body,
+                        // which is on the fallthrough edge.
+                        DisJump::OnInit
+                        | DisJump::OnInteract(_)
+                        | DisJump::OnKey { .. }
+                        | DisJump::OnCombine { .. }
+                        | DisJump::UnknownFallthrough => {
+                            if let Some(pos) = cline {
+                                found.push(AstHandler { pos: *pos, conditions: conditions.clone(), body });
+                            }
+                        }
+                        _ => (),
+                    }
+                    nested(body, conditions, extra, found);
+                }
+            }
+            AstToken::Switch(test, cases) => {
+                for (_, value, body) in cases {
+                    nested(body, conditions, vec![Expr::binop(BinOp::Eq, test.clone(), value.clone())], found);
+                }
+            }
+            AstToken::While(_, test, body) | AstToken::For(_, _, test, _, body) => nested(body, conditions, vec![test.clone()], found),
+            AstToken::Loop(body) | AstToken::DoWhile(_, _, body) => nested(body, conditions, vec![], found),
+            _ => (),
+        }
+    }
+}
+
+/// Whether `test` compares `var` to something.
+fn compares(test: &Expr, var: &Expr) -> bool {
+    let strip = |expr: &Expr| match expr {
+        Expr::Hint(box expr, _) => expr.clone(),
+        _ => expr.clone(),
+    };
+    match test {
+        Expr::Binop(op, lhs, rhs) if op.negated().is_some() => strip(lhs) == *var || strip(rhs) == *var,
+        _ => false,
+    }
+}
+
+/// Outputs the loop with the given body, which is exited with a `break`.
+/// `output` is the code preceding the loop, from which the initialisation of
+/// a counter loop is taken.
+pub(super) fn make_loop(output: &mut Vec<AstToken>, mut content: Vec<AstToken>) {
     // identify while loops
     if content.len() == 2
-        && matches!(&content[0], AstToken::Chain(b) if b.len() == 1 && b[0].1.starts_with("if ") && b[0].3.ends_with(&[AstToken::Continue]))
+        && matches!(&content[0], AstToken::Chain(b) if b.len() == 1 && matches!(b[0].1, DisJump::Conditional { .. }) && b[0].3.ends_with(&[AstToken::Continue]))
         && content[1] == AstToken::Break {
         let AstToken::Break = content.pop().unwrap() else { unreachable!(); };
         let AstToken::Chain(mut branches) = content.pop().unwrap() else { unreachable!(); };
-        let (cline, cond, _, mut nested) = branches.pop().unwrap();
+        let (cline, DisJump::Conditional { test }, _, mut nested) = branches.pop().unwrap() else { unreachable!(); };
         let AstToken::Continue = nested.pop().unwrap() else { unreachable!(); };
         // convert a while (..) { tick } loop to wait while
         if nested.len() == 1 && matches!(nested[0], AstToken::Tick(..)) {
             let AstToken::Tick(cline) = nested[0] else { unreachable!(); };
-            return AstToken::Line(
-                cline,
-                format!("<span class=\"hl-kw\">wait while</span> {}", &cond["if ".len()..]),
-            );
+            output.push(AstToken::WaitWhile(cline, test));
+            return;
         }
-        return AstToken::While(
-            cline,
-            cond["if ".len()..].to_string(),
-            nested,
-        );
+        // convert a while loop over a counter, which is set just before the
+        // loop and incremented at the end of the body, to a for loop
+        if let Some(AstToken::Line(_, Stmt::Update(_, false, var @ Expr::Global(..)))) = nested.last()
+            && let Some(AstToken::Line(Some(_), Stmt::Assign(init_var, "=", _))) = output.last()
+            && init_var == var
+            && compares(&test, var)
+            && !continues(&nested[..nested.len() - 1]) {
+            let Some(AstToken::Line(_, update)) = nested.pop() else { unreachable!(); };
+            let Some(AstToken::Line(init_cline, init)) = output.pop() else { unreachable!(); };
+            output.push(AstToken::For(init_cline, init, test, update, nested));
+            return;
+        }
+        output.push(AstToken::While(cline, test, nested));
+        return;
+    }
+    // identify do-while loops, where the loop is continued from its end
+    if content.len() >= 2
+        && content.last() == Some(&AstToken::Break)
+        && let AstToken::Chain(branches) = &content[content.len() - 2]
+        && let Some((cline, test)) = continue_test(branches)
+        && !continues(&content[..content.len() - 2]) {
+        content.truncate(content.len() - 2);
+        output.push(AstToken::DoWhile(cline, test, content));
+        return;
     }
-    AstToken::Loop(content)
+    output.push(AstToken::Loop(content));
 }
 
+/// Renders the AST, numbering its blocks from `block_counter`.
 pub(super) fn build(
     code_start: usize,
+    style: &dyn Style,
     ast: &[AstToken],
     output: &mut String,
     depth: usize,
@@ -145,47 +278,92 @@ pub(super) fn build(
     let addr_indent = "  ".repeat(depth);
     let indent = "  ".repeat(depth + 3);
     let cline_indent = |cline: Option<usize>| if let Some(addr) = cline {
-        format!("{}  {addr_indent}", show_addr(addr + code_start))
+        format!("{}  {addr_indent}", style.addr(addr + code_start))
     } else {
         indent.to_string()
     };
+    let expr = |expr: &Expr| {
+        let mut output = String::new();
+        write_expr(&mut output, expr, style);
+        output
+    };
+    let jump = |kind: &DisJump| {
+        let mut output = String::new();
+        write_jump(&mut output, kind, style);
+        output
+    };
     for token in ast {
         match token {
             AstToken::Line(cline, line) => {
+                let mut rendered = String::new();
+                write_stmt(&mut rendered, line, style);
                 output.push_str(&cline_indent(*cline));
-                output.push_str(&line.split("\n").collect::<Vec<_>>().join(&format!("\n{indent}")));
+                output.push_str(&rendered.split("\n").collect::<Vec<_>>().join(&format!("\n{indent}")));
                 output.push('\n');
             }
             AstToken::Break => {
                 output.push_str(&indent);
-                output.push_str("<span class=\"hl-kw\">break</span>\n");
+                output.push_str(&style.keyword("break"));
+                output.push('\n');
             }
             AstToken::Continue => {
                 output.push_str(&indent);
-                output.push_str("<span class=\"hl-kw\">continue</span>\n");
+                output.push_str(&style.keyword("continue"));
+                output.push('\n');
             }
             AstToken::Exit(cline) => {
                 output.push_str(&cline_indent(*cline));
-                output.push_str("<span class=\"hl-kw\">exit</span>\n");
+                output.push_str(&style.keyword("exit"));
+                output.push('\n');
             }
             AstToken::Tick(cline) => {
                 output.push_str(&cline_indent(*cline));
-                output.push_str("<span class=\"hl-kw\">tick</span>\n");
+                output.push_str(&style.keyword("tick"));
+                output.push('\n');
             }
             AstToken::Loop(ast) => {
                 let bid = *block_counter;
                 *block_counter += 1;
-                output.push_str(&format!("{indent}<span class=\"hl-kw\">loop</span> <a href=\"#be-{bid}\" id=\"bb-{bid}\">{{</a>\n"));
-                build(code_start, ast, output, depth + 1, block_counter);
-                output.push_str(&format!("{indent}<a href=\"#bb-{bid}\" id=\"be-{bid}\">}}</a>\n"));
+                output.push_str(&format!("{indent}{} {}\n", style.keyword("loop"), style.open_block(bid)));
+                build(code_start, style, ast, output, depth + 1, block_counter);
+                output.push_str(&format!("{indent}{}\n", style.close_block(bid)));
             }
             AstToken::While(cline, cond, ast) => {
                 output.push_str(&cline_indent(*cline));
                 let bid = *block_counter;
                 *block_counter += 1;
-                output.push_str(&format!("<span class=\"hl-kw\">while</span> {cond} <a href=\"#be-{bid}\" id=\"bb-{bid}\">{{</a>\n"));
-                build(code_start, ast, output, depth + 1, block_counter);
-                output.push_str(&format!("{indent}<a href=\"#bb-{bid}\" id=\"be-{bid}\">}}</a>\n"));
+                output.push_str(&format!("{} ({}) {}\n", style.keyword("while"), expr(cond), style.open_block(bid)));
+                build(code_start, style, ast, output, depth + 1, block_counter);
+                output.push_str(&format!("{indent}{}\n", style.close_block(bid)));
+            }
+            AstToken::DoWhile(cline, cond, ast) => {
+                let bid = *block_counter;
+                *block_counter += 1;
+                output.push_str(&format!("{indent}{} {}\n", style.keyword("do"), style.open_block(bid)));
+                build(code_start, style, ast, output, depth + 1, block_counter);
+                output.push_str(&cline_indent(*cline));
+                output.push_str(&format!("{} {} ({})\n", style.close_block(bid), style.keyword("while"), expr(cond)));
+            }
+            AstToken::For(cline, init, cond, update, ast) => {
+                output.push_str(&cline_indent(*cline));
+                let bid = *block_counter;
+                *block_counter += 1;
+                let mut init_rendered = String::new();
+                write_stmt(&mut init_rendered, init, style);
+                let mut update_rendered = String::new();
+                write_stmt(&mut update_rendered, update, style);
+                output.push_str(&format!(
+                    "{} ({init_rendered}; {}; {update_rendered}) {}\n",
+                    style.keyword("for"),
+                    expr(cond),
+                    style.open_block(bid),
+                ));
+                build(code_start, style, ast, output, depth + 1, block_counter);
+                output.push_str(&format!("{indent}{}\n", style.close_block(bid)));
+            }
+            AstToken::WaitWhile(cline, cond) => {
+                output.push_str(&cline_indent(*cline));
+                output.push_str(&format!("{} ({})\n", style.keyword("wait while"), expr(cond)));
             }
             AstToken::Chain(branches) => {
                 let mut bid = 0;
@@ -194,27 +372,43 @@ pub(super) fn build(
                     bid = *block_counter;
                     *block_counter += 1;
                     if idx > 0 {
-                        output.push_str(&format!("<a href=\"#bb-{}\" id=\"be-{}\">}}</a> <span class=\"hl-kw\">else</span> ", bid - 1, bid - 1));
+                        output.push_str(&format!("{} {} ", style.close_block(bid - 1), style.keyword("else")));
                     }
-                    if let Some(cond) = cond.strip_prefix("if ") {
-                        output.push_str(&format!("<span class=\"hl-kw\">if</span> {cond} <a href=\"#be-{bid}\" id=\"bb-{bid}\">{{</a>\n"));
+                    if let DisJump::Conditional { test } = cond {
+                        output.push_str(&format!("{} ({}) {}\n", style.keyword("if"), expr(test), style.open_block(bid)));
                     } else {
-                        output.push_str(&format!("{cond} <a href=\"#be-{bid}\" id=\"bb-{bid}\">{{</a>\n"));
+                        output.push_str(&format!("{} {}\n", jump(cond), style.open_block(bid)));
                     }
-                    build(code_start, branch, output, depth + 1, block_counter);
+                    build(code_start, style, branch, output, depth + 1, block_counter);
                 }
-                output.push_str(&format!("{indent}<a href=\"#bb-{}\" id=\"be-{}\">}}</a>\n", bid, bid));
+                output.push_str(&format!("{indent}{}\n", style.close_block(bid)));
+            }
+            AstToken::Label(addr) => {
+                output.push_str(&format!("{addr_indent}    {}:\n", style.label(addr + code_start)));
+            }
+            AstToken::Goto(cline, kind, addr) => {
+                output.push_str(&cline_indent(*cline));
+                if let Some(kind) = kind {
+                    output.push_str(&jump(kind));
+                    output.push(' ');
+                }
+                output.push_str(&format!("{} {}\n", style.keyword("goto"), style.label_ref(addr + code_start)));
+            }
+            AstToken::Folded(cline, test, value) => {
+                output.push_str(&cline_indent(*cline));
+                output.push_str(&style.comment(&format!("// always {value}: {test}")));
+                output.push('\n');
             }
             AstToken::Switch(test, cases) => {
                 let bid = *block_counter;
                 *block_counter += 1;
-                output.push_str(&format!("{indent}<span class=\"hl-kw\">switch</span> ({test}) <a href=\"#be-{bid}\" id=\"bb-{bid}\">{{</a>\n"));
+                output.push_str(&format!("{indent}{} ({}) {}\n", style.keyword("switch"), expr(test), style.open_block(bid)));
                 for (cline, value, branch) in cases {
                     output.push_str(&cline_indent(*cline));
-                    output.push_str(&format!("<span class=\"hl-kw\">case</span> {value}:\n"));
-                    build(code_start, branch, output, depth + 1, block_counter);
+                    output.push_str(&format!("{} {}:\n", style.keyword("case"), expr(value)));
+                    build(code_start, style, branch, output, depth + 1, block_counter);
                 }
-                output.push_str(&format!("{indent}<a href=\"#bb-{bid}\" id=\"be-{bid}\">}}</a>\n"));
+                output.push_str(&format!("{indent}{}\n", style.close_block(bid)));
             }
         }
     }
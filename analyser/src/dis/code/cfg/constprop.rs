@@ -0,0 +1,297 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::dis::code::{ir::{BinOp, Expr, Stmt, UnOp}, DisJump};
+
+use super::{AstToken, BlockEdge, BlockId, CfgAnalysis};
+
+/// Definition of a global reaching a point in the code.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Def {
+    /// Value set outside of the analysed path: before the code started, by
+    /// another script running during a `tick`, by a call, ...
+    Unknown,
+    /// Line `idx` of the given block.
+    At(BlockId, usize),
+}
+
+/// Definitions of each global reaching a point. Globals which are not in
+/// the map are only reached by `Def::Unknown`.
+#[derive(Clone, PartialEq, Default, Debug)]
+struct Reaching(BTreeMap<String, BTreeSet<Def>>);
+
+/// Reaching definitions of globals, with the constant value of each
+/// definition, if it has one.
+#[derive(Default)]
+struct ReachingDefs {
+    at_start: HashMap<BlockId, Reaching>,
+    /// Value of every definition, or `None` once it is known to vary.
+    values: HashMap<(BlockId, usize), Option<u32>>,
+}
+
+impl ReachingDefs {
+    fn value(&self, reaching: &Reaching, name: &str) -> Option<u32> {
+        let defs = reaching.0.get(name)?;
+        let mut values = defs.iter().map(|def| match def {
+            Def::Unknown => None,
+            Def::At(block, idx) => self.values.get(&(*block, *idx)).copied().flatten(),
+        });
+        let first = values.next()??;
+        values.all(|value| value == Some(first)).then_some(first)
+    }
+
+    /// Evaluates an integer expression, with the constant globals.
+    fn eval(&self, reaching: &Reaching, expr: &Expr) -> Option<u32> {
+        match expr {
+            Expr::Int(value) => Some(*value),
+            Expr::Hint(value, _) => self.eval(reaching, value),
+            Expr::Global(box Expr::Str(name)) => self.value(reaching, name),
+            Expr::Binop(op, lhs, rhs) => op.apply(self.eval(reaching, lhs)?, self.eval(reaching, rhs)?),
+            Expr::Unop(UnOp::Neg, value) => Some(self.eval(reaching, value)?.wrapping_neg()),
+            Expr::Unop(UnOp::BitNot, value) => Some(!self.eval(reaching, value)?),
+            Expr::Unop(UnOp::Not, value) => Some((self.eval(reaching, value)? == 0) as u32),
+            _ => None,
+        }
+    }
+
+    /// Replaces constant globals converted to strings, as in clone names,
+    /// with the resulting string. Other uses of globals are kept, since the
+    /// name says more than the value.
+    fn substitute(&self, reaching: &Reaching, expr: &Expr) -> Expr {
+        let sub = |expr: &Expr| Box::new(self.substitute(reaching, expr));
+        match expr {
+            Expr::Unop(UnOp::ToStr, value) => match self.eval(reaching, value) {
+                Some(value) => Expr::Str(value.to_string()),
+                None => Expr::Unop(UnOp::ToStr, sub(value)),
+            },
+            // Only concatenations with a substituted value are joined, others
+            // are kept as the code wrote them.
+            Expr::Binop(BinOp::Concat, lhs, rhs) => match (*sub(lhs), *sub(rhs)) {
+                (Expr::Str(new_lhs), Expr::Str(new_rhs)) if Expr::Str(new_lhs.clone()) != **lhs || Expr::Str(new_rhs.clone()) != **rhs => {
+                    Expr::Str(new_lhs + &new_rhs)
+                }
+                (lhs, rhs) => Expr::binop(BinOp::Concat, lhs, rhs),
+            },
+            Expr::Binop(op, lhs, rhs) => Expr::Binop(*op, sub(lhs), sub(rhs)),
+            Expr::Unop(op, value) => Expr::Unop(*op, sub(value)),
+            Expr::Global(name) => Expr::Global(sub(name)),
+            Expr::Index(base, index) => Expr::Index(base, sub(index)),
+            Expr::Field(base, name) => Expr::Field(sub(base), name),
+            Expr::Call(func, args) => Expr::Call(
+                sub(func),
+                args.iter().map(|(label, arg)| (*label, *sub(arg))).collect(),
+            ),
+            Expr::Tuple(items) => Expr::Tuple(items.iter().map(|(label, item)| (*label, *sub(item))).collect()),
+            Expr::Hint(value, hint) => Expr::Hint(sub(value), hint.clone()),
+            _ => expr.clone(),
+        }
+    }
+
+    fn substitute_stmt(&self, reaching: &Reaching, stmt: &Stmt) -> Stmt {
+        let sub = |expr: &Expr| self.substitute(reaching, expr);
+        match stmt {
+            Stmt::Assign(lhs, op, rhs) => Stmt::Assign(sub(lhs), op, sub(rhs)),
+            Stmt::Update(op, prefix, value) => Stmt::Update(op, *prefix, sub(value)),
+            Stmt::Expr(expr) => Stmt::Expr(sub(expr)),
+            Stmt::Record(name, fields) => Stmt::Record(name, fields.iter().map(|(label, value)| (*label, sub(value))).collect()),
+            Stmt::Unknown(name, guess, args) => Stmt::Unknown(name, *guess, args.iter().map(sub).collect()),
+            Stmt::Push(stmt) => Stmt::Push(Box::new(self.substitute_stmt(reaching, stmt))),
+            _ => stmt.clone(),
+        }
+    }
+
+    /// Records the value of a definition. Returns whether it changed.
+    fn define(&mut self, key: (BlockId, usize), value: Option<u32>) -> bool {
+        let value = match self.values.get(&key) {
+            None => value,
+            Some(prev) if *prev == value => return false,
+            // Once a definition is known to vary, it stays so, which
+            // guarantees that the analysis terminates.
+            Some(_) => None,
+        };
+        self.values.insert(key, value) != Some(value)
+    }
+
+    /// Applies the lines of a block to the definitions reaching its start.
+    /// With `rewrite`, the lines are also replaced with their substituted
+    /// form. Returns whether the value of any definition changed.
+    fn transfer(&mut self, id: BlockId, lines: &mut [AstToken], reaching: &mut Reaching, rewrite: bool) -> bool {
+        let mut changed = false;
+        for (idx, line) in lines.iter_mut().enumerate() {
+            let stmt = match line {
+                AstToken::Line(_, stmt) => stmt,
+                AstToken::Tick(_) => {
+                    reaching.0.clear();
+                    continue;
+                }
+                _ => continue,
+            };
+            let new = self.substitute_stmt(reaching, stmt);
+            let def = match &new {
+                Stmt::Assign(Expr::Global(box Expr::Str(name)), op, value) => {
+                    let value = match op.strip_suffix('=') {
+                        Some("") => self.eval(reaching, value),
+                        Some(symbol) => binop_by_symbol(symbol).and_then(|op| {
+                            op.apply(self.value(reaching, name)?, self.eval(reaching, value)?)
+                        }),
+                        None => None,
+                    };
+                    Some((name.clone(), value))
+                }
+                Stmt::Update(op, _, Expr::Global(box Expr::Str(name))) => {
+                    let value = self.value(reaching, name).map(|value| match *op {
+                        "++" => value.wrapping_add(1),
+                        _ => value.wrapping_sub(1),
+                    });
+                    Some((name.clone(), value))
+                }
+                // Properties of objects are set without running scripts.
+                Stmt::Assign(lhs, ..) if !matches!(lhs, Expr::Global(_)) => None,
+                Stmt::Jump(..) | Stmt::Exit | Stmt::Quit => None,
+                // Any global could be written, or the engine could run other
+                // scripts, e.g. while waiting for a dialogue to finish.
+                _ => {
+                    reaching.0.clear();
+                    None
+                }
+            };
+            if rewrite {
+                *stmt = new;
+            }
+            let Some((name, value)) = def else {
+                continue;
+            };
+            changed |= self.define((id, idx), value);
+            reaching.0.insert(name, [Def::At(id, idx)].into());
+        }
+        changed
+    }
+
+    /// Result of the conditional jump out of a block, if it is constant.
+    fn branch(&self, edges: &[BlockEdge], reaching: &Reaching) -> Option<bool> {
+        let mut tests = edges.iter()
+            .filter_map(|edge| match &edge.kind {
+                DisJump::Conditional { test } => Some(test),
+                _ => None,
+            });
+        match (tests.next(), tests.next()) {
+            (Some(test), None) => Some(self.eval(reaching, test)? != 0),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `edge` is taken when the conditional jump has the given result.
+/// The fallthrough edge carries the test.
+fn is_taken(edge: &BlockEdge, result: Option<bool>) -> bool {
+    result.is_none_or(|result| matches!(edge.kind, DisJump::Conditional { .. }) == result)
+}
+
+fn binop_by_symbol(symbol: &str) -> Option<BinOp> {
+    [
+        BinOp::Add, BinOp::Sub, BinOp::Mul, BinOp::Div, BinOp::Mod, BinOp::BitAnd,
+        BinOp::BitOr, BinOp::Xor, BinOp::Shl, BinOp::Shr,
+    ].into_iter().find(|op| op.symbol() == symbol)
+}
+
+impl CfgAnalysis {
+    fn reaching_defs(&mut self) -> ReachingDefs {
+        let mut defs = ReachingDefs::default();
+        defs.at_start.insert(0.into(), Reaching::default());
+        let mut order = self.blocks.keys().copied().collect::<Vec<_>>();
+        order.sort();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in &order {
+                let Some(mut reaching) = defs.at_start.get(id).cloned() else {
+                    continue;
+                };
+                let block = self.blocks.get_mut(id).unwrap();
+                changed |= defs.transfer(*id, &mut block.lines, &mut reaching, false);
+                let result = defs.branch(&block.succ, &reaching);
+                for edge in &block.succ {
+                    if edge.to == BlockId::End || !is_taken(edge, result) {
+                        continue;
+                    }
+                    // Event handlers run later, and the effect of unknown
+                    // jumps is not known.
+                    let reaching = match edge.kind {
+                        DisJump::OnInit
+                        | DisJump::OnInteract(_)
+                        | DisJump::OnKey { .. }
+                        | DisJump::OnCombine { .. }
+                        | DisJump::Unknown { .. }
+                        | DisJump::UnknownFallthrough => Reaching::default(),
+                        _ => reaching.clone(),
+                    };
+                    let joined = match defs.at_start.get(&edge.to) {
+                        None => reaching,
+                        Some(prev) => join(prev, &reaching),
+                    };
+                    if defs.at_start.get(&edge.to) != Some(&joined) {
+                        defs.at_start.insert(edge.to, joined);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        defs
+    }
+
+    /// Propagates constant values of globals through the code, using the
+    /// definitions reaching each point. Globals converted to strings, such
+    /// as in clone names, are replaced with their value. Conditional jumps
+    /// which always go the same way are replaced with a comment, and the
+    /// branch which is never taken is removed.
+    pub(super) fn propagate_constants(&mut self) {
+        let mut defs = self.reaching_defs();
+        let mut ids = self.blocks.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        for id in ids {
+            let Some(mut reaching) = defs.at_start.get(&id).cloned() else {
+                continue;
+            };
+            let block = self.blocks.get_mut(&id).unwrap();
+            defs.transfer(id, &mut block.lines, &mut reaching, true);
+            let Some(result) = defs.branch(&block.succ, &reaching) else {
+                for edge in &mut block.succ {
+                    if let DisJump::Conditional { test } = &edge.kind {
+                        edge.kind = DisJump::Conditional { test: defs.substitute(&reaching, test) };
+                    }
+                }
+                continue;
+            };
+            let test = block.succ.iter()
+                .find_map(|edge| match &edge.kind {
+                    DisJump::Conditional { test } => Some(test.clone()),
+                    _ => None,
+                })
+                .unwrap();
+            let (mut taken, dead): (Vec<_>, Vec<_>) = block.succ.drain(..)
+                .partition(|edge| is_taken(edge, Some(result)));
+            block.lines.push(AstToken::Folded(taken[0].line, test, result));
+            taken[0].kind = DisJump::Unconditional;
+            let to = taken[0].to;
+            block.succ.extend(taken);
+            for edge in dead {
+                if edge.to != to && let Some(target) = self.blocks.get_mut(&edge.to) {
+                    target.pred.remove(&id);
+                }
+            }
+        }
+    }
+}
+
+fn join(a: &Reaching, b: &Reaching) -> Reaching {
+    let unknown = BTreeSet::from([Def::Unknown]);
+    let names = a.0.keys().chain(b.0.keys()).collect::<BTreeSet<_>>();
+    Reaching(names.into_iter()
+        .map(|name| {
+            let defs = a.0.get(name).unwrap_or(&unknown)
+                .union(b.0.get(name).unwrap_or(&unknown))
+                .copied()
+                .collect();
+            (name.clone(), defs)
+        })
+        .collect())
+}
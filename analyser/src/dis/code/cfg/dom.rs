@@ -0,0 +1,127 @@
+use std::collections::{HashMap, HashSet};
+
+use super::BlockId;
+
+/// Dominator tree, computed with the Cooper–Harvey–Kennedy algorithm ("A
+/// Simple, Fast Dominance Algorithm"). Used for postdominators by passing the
+/// reversed graph.
+#[derive(Default)]
+pub(super) struct DomTree {
+    /// Immediate dominator of every node reachable from the root. The root is
+    /// its own immediate dominator.
+    idom: HashMap<BlockId, BlockId>,
+
+    /// Preorder and postorder number of every node in the dominator tree, so
+    /// that dominance can be checked without walking up the tree.
+    order: HashMap<BlockId, (usize, usize)>,
+}
+
+impl DomTree {
+    pub(super) fn new(
+        root: BlockId,
+        succ: impl Fn(BlockId) -> Vec<BlockId>,
+        pred: impl Fn(BlockId) -> Vec<BlockId>,
+    ) -> Self {
+        // Number the nodes in postorder, iteratively to not overflow the stack
+        // on large objects.
+        let mut postorder = Vec::new();
+        let mut visited = HashSet::from([root]);
+        let mut stack = vec![(root, succ(root), 0)];
+        while let Some((id, next, idx)) = stack.last_mut() {
+            if let Some(to) = next.get(*idx).copied() {
+                *idx += 1;
+                if visited.insert(to) {
+                    stack.push((to, succ(to), 0));
+                }
+            } else {
+                postorder.push(*id);
+                stack.pop();
+            }
+        }
+        let index = postorder.iter()
+            .enumerate()
+            .map(|(idx, id)| (*id, idx))
+            .collect::<HashMap<_, _>>();
+
+        // Indices into `postorder`.
+        let mut doms: Vec<Option<usize>> = vec![None; postorder.len()];
+        let root_idx = index[&root];
+        doms[root_idx] = Some(root_idx);
+        let intersect = |doms: &[Option<usize>], mut a: usize, mut b: usize| {
+            while a != b {
+                while a < b {
+                    a = doms[a].unwrap();
+                }
+                while b < a {
+                    b = doms[b].unwrap();
+                }
+            }
+            a
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for idx in (0..root_idx).rev() {
+                let new = pred(postorder[idx])
+                    .into_iter()
+                    .filter_map(|id| index.get(&id).copied())
+                    .filter(|pidx| doms[*pidx].is_some())
+                    .reduce(|a, b| intersect(&doms, a, b));
+                if new.is_some() && doms[idx] != new {
+                    doms[idx] = new;
+                    changed = true;
+                }
+            }
+        }
+
+        // Nodes without a dominator were reached through an edge missing from
+        // `pred`, and are left out.
+        let idom = postorder.iter()
+            .zip(&doms)
+            .filter_map(|(id, dom)| Some((*id, postorder[(*dom)?])))
+            .collect::<HashMap<_, _>>();
+
+        // Number the dominator tree.
+        let mut children: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+        for (id, dom) in &idom {
+            if id != dom {
+                children.entry(*dom).or_default().push(*id);
+            }
+        }
+        let mut order = HashMap::new();
+        let mut counter = 0;
+        let mut stack = vec![(root, false)];
+        while let Some((id, done)) = stack.pop() {
+            if done {
+                if let Some((_, post)) = order.get_mut(&id) {
+                    *post = counter;
+                }
+                counter += 1;
+                continue;
+            }
+            order.insert(id, (counter, 0));
+            counter += 1;
+            stack.push((id, true));
+            stack.extend(children.get(&id).into_iter().flatten().map(|id| (*id, false)));
+        }
+        Self { idom, order }
+    }
+
+    /// Whether `a` dominates `b`. Every node dominates itself. Nodes not
+    /// reachable from the root are dominated by everything, as if the
+    /// iterative dataflow definition were applied to them.
+    pub(super) fn dominates(&self, a: BlockId, b: BlockId) -> bool {
+        let Some((b_pre, b_post)) = self.order.get(&b) else {
+            return true;
+        };
+        let Some((a_pre, a_post)) = self.order.get(&a) else {
+            return false;
+        };
+        a_pre <= b_pre && b_post <= a_post
+    }
+
+    /// Whether `id` is reachable from the root.
+    pub(super) fn contains(&self, id: BlockId) -> bool {
+        self.idom.contains_key(&id)
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use crate::dis::code::DisJump;
+
+use super::{AstToken, BlockId, CfgAnalysis};
+
+/// Statements shown in a block before it is cut off.
+const MAX_NODE_LINES: usize = 12;
+
+/// Characters shown per statement before it is cut off.
+const MAX_NODE_CHARS: usize = 48;
+
+// SVG layout metrics, in pixels.
+const CHAR_WIDTH: usize = 7;
+const LINE_HEIGHT: usize = 14;
+const NODE_PADDING: usize = 6;
+const NODE_GAP: usize = 30;
+const RANK_GAP: usize = 50;
+const MARGIN: usize = 10;
+const BACK_EDGE_MARGIN: usize = 80;
+
+/// File format for control-flow graphs.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GraphFormat {
+    Dot,
+    Svg,
+}
+
+impl GraphFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Dot => "dot",
+            Self::Svg => "svg",
+        }
+    }
+}
+
+/// Control-flow graph of a code object, as built by the decompiler: basic
+/// blocks and the edges between them, labelled with their jump kinds.
+pub struct CfgGraph {
+    nodes: Vec<CfgNode>,
+    edges: Vec<CfgEdge>,
+}
+
+struct CfgNode {
+    /// Offset of the first instruction, in the object.
+    addr: usize,
+    lines: Vec<String>,
+}
+
+struct CfgEdge {
+    from: usize,
+    to: usize,
+    kind: CfgEdgeKind,
+    label: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum CfgEdgeKind {
+    Straight,
+    Handler,
+    Branch,
+    Fallthrough,
+}
+
+impl CfgEdgeKind {
+    fn of(jump: &DisJump) -> Self {
+        match jump {
+            DisJump::Straight | DisJump::Unconditional => Self::Straight,
+            DisJump::OnInit | DisJump::OnInteract(..) | DisJump::OnKey { .. } | DisJump::OnCombine { .. } => Self::Handler,
+            DisJump::Conditional { .. } | DisJump::Unknown { .. } => Self::Branch,
+            _ => Self::Fallthrough,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Straight => "straight",
+            Self::Handler => "handler",
+            Self::Branch => "branch",
+            Self::Fallthrough => "fallthrough",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Self::Straight => "#999",
+            Self::Handler => "#68f",
+            Self::Branch => "#6b6",
+            Self::Fallthrough => "#b66",
+        }
+    }
+}
+
+impl CfgAnalysis {
+    pub(super) fn graph(&self) -> CfgGraph {
+        let mut ids = self.blocks.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        let index = ids.iter()
+            .enumerate()
+            .map(|(idx, id)| (*id, idx))
+            .collect::<HashMap<_, _>>();
+        let mut nodes = Vec::new();
+        let mut edges = Vec::new();
+        for (idx, id) in ids.iter().enumerate() {
+            let block = self.blocks.get(id).unwrap();
+            let mut lines = block.lines.iter()
+                .filter_map(|token| match token {
+                    AstToken::Line(_, stmt) => Some(stmt.to_string()),
+                    AstToken::Exit(_) => Some("exit".to_string()),
+                    AstToken::Tick(_) => Some("tick".to_string()),
+                    _ => None,
+                })
+                .flat_map(|line| line.lines().map(|l| l.to_string()).collect::<Vec<_>>())
+                .map(|line| if line.chars().count() > MAX_NODE_CHARS {
+                    line.chars().take(MAX_NODE_CHARS - 1).collect::<String>() + "…"
+                } else {
+                    line
+                })
+                .collect::<Vec<_>>();
+            if lines.len() > MAX_NODE_LINES {
+                lines.truncate(MAX_NODE_LINES - 1);
+                lines.push("…".to_string());
+            }
+            nodes.push(CfgNode {
+                addr: self.code_start + block.start,
+                lines,
+            });
+            for edge in &block.succ {
+                // The code end is implied by `exit`.
+                let BlockId::Block(_) = edge.to else { continue; };
+                let kind = CfgEdgeKind::of(&edge.kind);
+                edges.push(CfgEdge {
+                    from: idx,
+                    to: index[&edge.to],
+                    kind,
+                    label: match kind {
+                        CfgEdgeKind::Straight => None,
+                        CfgEdgeKind::Fallthrough => Some("else".to_string()),
+                        _ => Some(edge.kind.to_string()),
+                    },
+                });
+            }
+        }
+        CfgGraph { nodes, edges }
+    }
+}
+
+impl CfgGraph {
+    /// Renders the graph in the Graphviz DOT language.
+    pub fn dot(&self, title: &str) -> String {
+        fn escape(s: &str) -> String {
+            s.replace('\\', "\\\\").replace('"', "\\\"")
+        }
+        let mut output = format!("digraph \"{}\" {{\n", escape(title));
+        output.push_str("    node [shape=box, fontname=\"monospace\", fontsize=10];\n");
+        output.push_str("    edge [fontname=\"monospace\", fontsize=9];\n");
+        for node in &self.nodes {
+            let mut label = format!("{:04x}\\l", node.addr);
+            for line in &node.lines {
+                label.push_str(&escape(line));
+                label.push_str("\\l");
+            }
+            output.push_str(&format!("    b{:04x} [label=\"{label}\"];\n", node.addr));
+        }
+        for edge in &self.edges {
+            let mut attrs = vec![format!("color=\"{}\"", edge.kind.color())];
+            if let Some(label) = &edge.label {
+                attrs.push(format!("label=\"{}\"", escape(label)));
+                attrs.push(format!("fontcolor=\"{}\"", edge.kind.color()));
+            }
+            match edge.kind {
+                CfgEdgeKind::Handler => attrs.push("penwidth=2".to_string()),
+                CfgEdgeKind::Fallthrough => attrs.push("style=dashed".to_string()),
+                _ => (),
+            }
+            output.push_str(&format!(
+                "    b{:04x} -> b{:04x} [{}];\n",
+                self.nodes[edge.from].addr,
+                self.nodes[edge.to].addr,
+                attrs.join(", "),
+            ));
+        }
+        output.push_str("}\n");
+        output
+    }
+
+    /// Renders the graph as a standalone SVG image. Blocks are ranked by the
+    /// longest path of forward jumps leading to them, and placed in rows in
+    /// address order. Back edges are routed along the right side. Each block
+    /// links to its address in the bytecode listing.
+    pub fn svg(&self) -> String {
+        fn escape(s: &str) -> String {
+            s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+        }
+
+        // Rank blocks. Forward edges always go to a later block, so a single
+        // pass in address order suffices.
+        let mut rank = vec![0; self.nodes.len()];
+        for from in 0..self.nodes.len() {
+            for edge in self.edges.iter().filter(|e| e.from == from && e.to > e.from) {
+                rank[edge.to] = rank[edge.to].max(rank[from] + 1);
+            }
+        }
+        let rank_count = rank.iter().max().map(|r| r + 1).unwrap_or(0);
+
+        // Place blocks.
+        let size = self.nodes.iter()
+            .map(|node| {
+                let chars = node.lines.iter().map(|l| l.chars().count()).max().unwrap_or(0).max(4);
+                (chars * CHAR_WIDTH + 2 * NODE_PADDING, (node.lines.len() + 1) * LINE_HEIGHT + 2 * NODE_PADDING)
+            })
+            .collect::<Vec<_>>();
+        let rows = (0..rank_count)
+            .map(|r| (0..self.nodes.len()).filter(|idx| rank[*idx] == r).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let row_width = |row: &[usize]| row.iter().map(|idx| size[*idx].0).sum::<usize>() + NODE_GAP * row.len().saturating_sub(1);
+        let content_width = rows.iter().map(|row| row_width(row)).max().unwrap_or(0);
+        let mut pos = vec![(0, 0); self.nodes.len()];
+        let mut y = MARGIN;
+        for row in &rows {
+            let mut x = MARGIN + (content_width - row_width(row)) / 2;
+            for idx in row {
+                pos[*idx] = (x, y);
+                x += size[*idx].0 + NODE_GAP;
+            }
+            y += row.iter().map(|idx| size[*idx].1).max().unwrap_or(0) + RANK_GAP;
+        }
+        let width = 2 * MARGIN + content_width + BACK_EDGE_MARGIN;
+        let height = y - RANK_GAP + MARGIN;
+
+        let mut svg = format!("<svg class=\"cfg\" width=\"{width}\" height=\"{height}\" xmlns=\"http://www.w3.org/2000/svg\">");
+        svg.push_str("<defs>");
+        for kind in [CfgEdgeKind::Straight, CfgEdgeKind::Handler, CfgEdgeKind::Branch, CfgEdgeKind::Fallthrough] {
+            svg.push_str(&format!(
+                "<marker id=\"cfg-arrow-{}\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"6\" markerHeight=\"6\" orient=\"auto-start-reverse\"><path d=\"M0 0L10 5L0 10z\" fill=\"{}\"/></marker>",
+                kind.name(),
+                kind.color(),
+            ));
+        }
+        svg.push_str("</defs>");
+
+        // Blocks.
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let ((x, y), (w, h)) = (pos[idx], size[idx]);
+            svg.push_str(&format!("<a href=\"#addr{:04x}\">", node.addr));
+            svg.push_str(&format!("<rect x=\"{x}\" y=\"{y}\" width=\"{w}\" height=\"{h}\" fill=\"#fff\" stroke=\"#999\"/>"));
+            svg.push_str(&format!("<text x=\"{}\" y=\"{}\" font-family=\"monospace\" font-size=\"12\">", x + NODE_PADDING, y + NODE_PADDING));
+            svg.push_str(&format!("<tspan x=\"{}\" dy=\"{LINE_HEIGHT}\" fill=\"#a00\">{:04x}</tspan>", x + NODE_PADDING, node.addr));
+            for line in &node.lines {
+                svg.push_str(&format!("<tspan x=\"{}\" dy=\"{LINE_HEIGHT}\" xml:space=\"preserve\">{}</tspan>", x + NODE_PADDING, escape(line)));
+            }
+            svg.push_str("</text></a>");
+        }
+
+        // Edges, with the labels drawn last so that they are not covered.
+        let mut labels = String::new();
+        for (idx, edge) in self.edges.iter().enumerate() {
+            let ((fx, fy), (fw, fh)) = (pos[edge.from], size[edge.from]);
+            let ((tx, ty), (tw, th)) = (pos[edge.to], size[edge.to]);
+            let (p0, p1, p2, p3) = if edge.to > edge.from {
+                // Spread out the edges leaving the same block.
+                let siblings = self.edges.iter().filter(|e| e.from == edge.from && e.to > e.from).count();
+                let nth = self.edges[..idx].iter().filter(|e| e.from == edge.from && e.to > e.from).count();
+                let sx = (fx + fw / 2) as f32 + (nth as f32 - (siblings - 1) as f32 / 2.0) * 20.0;
+                let (sy, ey) = ((fy + fh) as f32, ty as f32);
+                let ex = (tx + tw / 2) as f32;
+                let dy = (ey - sy) / 2.0;
+                ((sx, sy), (sx, sy + dy), (ex, ey - dy), (ex, ey))
+            } else {
+                let (sx, sy) = ((fx + fw) as f32, (fy + fh / 2) as f32);
+                let (ex, ey) = ((tx + tw) as f32, (ty + th / 2) as f32);
+                let dx = (width - MARGIN) as f32 - sx.max(ex);
+                ((sx, sy), (sx + dx, sy), (ex + dx, ey), (ex, ey))
+            };
+            svg.push_str(&format!(
+                "<path d=\"M{} {}C{} {},{} {},{} {}\" fill=\"none\" stroke=\"{}\"{} marker-end=\"url(#cfg-arrow-{})\"/>",
+                p0.0, p0.1, p1.0, p1.1, p2.0, p2.1, p3.0, p3.1,
+                edge.kind.color(),
+                match edge.kind {
+                    CfgEdgeKind::Handler => " stroke-width=\"2\"",
+                    CfgEdgeKind::Fallthrough => " stroke-dasharray=\"4 3\"",
+                    _ => "",
+                },
+                edge.kind.name(),
+            ));
+            if let Some(label) = &edge.label {
+                // Midpoint of the curve.
+                let mx = (p0.0 + 3.0 * p1.0 + 3.0 * p2.0 + p3.0) / 8.0;
+                let my = (p0.1 + 3.0 * p1.1 + 3.0 * p2.1 + p3.1) / 8.0;
+                labels.push_str(&format!(
+                    "<text x=\"{mx}\" y=\"{my}\" font-family=\"monospace\" font-size=\"11\" text-anchor=\"middle\" fill=\"{}\" stroke=\"#fff\" stroke-width=\"3\" paint-order=\"stroke\">{}</text>",
+                    edge.kind.color(),
+                    escape(label),
+                ));
+            }
+        }
+        svg.push_str(&labels);
+        svg.push_str("</svg>");
+        svg
+    }
+}
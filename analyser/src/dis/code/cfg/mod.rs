@@ -1,36 +1,43 @@
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::{cell::RefCell, collections::{HashMap, HashSet, VecDeque}, rc::Rc};
 
-use crate::dis::DisError;
+use crate::{dis::{DisError, DisRendered}, Resources};
 
-use super::{opcodes::DisIns, DisJump, DisOp};
+use super::{ir::{BinOp, Expr, Html, Stmt, Text}, opcodes::DisIns, opmap::OpcodeMap, DisJump, DisOp};
 
 mod ast;
 mod block;
+mod constprop;
+mod dom;
+mod graph;
 
 use ast::{AstStack, AstToken};
 use block::*;
+use dom::DomTree;
+pub use graph::{CfgGraph, GraphFormat};
 
 pub(crate) struct Decompiler<'a> {
     code_start: usize,
     code: &'a [u8],
+    map: &'a OpcodeMap,
     block_starts: HashSet<usize>,
-    pos_decomp: HashMap<usize, String>,
+    pos_decomp: HashMap<usize, Vec<Stmt>>,
     pos_jump: HashMap<usize, Vec<BlockEdge>>,
 }
 
 impl<'a> Decompiler<'a> {
-    pub(crate) fn new(code_start: usize, code: &'a [u8]) -> Self {
+    pub(crate) fn new(code_start: usize, code: &'a [u8], map: &'a OpcodeMap) -> Self {
         Self {
             code_start,
             code,
+            map,
             block_starts: [0].into(),
             pos_decomp: HashMap::new(),
             pos_jump: HashMap::new(),
         }
     }
 
-    pub(crate) fn add_decomp(&mut self, pos: usize, decomp: String) {
-        self.pos_decomp.insert(pos, decomp.clone());
+    pub(crate) fn add_decomp(&mut self, pos: usize, decomp: Vec<Stmt>) {
+        self.pos_decomp.insert(pos, decomp);
     }
 
     pub(crate) fn add_jump(&mut self, from: usize, to: usize, jump: DisJump) {
@@ -44,26 +51,76 @@ impl<'a> Decompiler<'a> {
         self.block_starts.insert(to);
     }
 
-    pub(crate) fn analyse(self) -> Result<String, DisError> {
+    pub(crate) fn graph(&self) -> Result<CfgGraph, DisError> {
         let mut analysis = CfgAnalysis::new(self.code_start);
-        analysis.create_blocks(self);
+        analysis.create_blocks(self)?;
+        Ok(analysis.graph())
+    }
+
+    /// Structures the code into readable script. Also returns the reasons
+    /// for the regions which could not be structured, and were printed with
+    /// labels and `goto`s instead, and the script of each event handler
+    /// which could be structured.
+    pub(crate) fn analyse(self, res: Resources) -> Result<(DisRendered, Vec<String>, Vec<HandlerScript>), DisError> {
+        let mut analysis = CfgAnalysis::new(self.code_start);
+        analysis.create_blocks(&self)?;
+        analysis.propagate_constants();
         analysis.compute_dominators();
-        analysis.walk(0.into(), BlockId::End)?;
-        Ok(analysis.build())
+        let loops = analysis.find_loops();
+        analysis.walk_root(&loops);
+        if !analysis.goto_targets.is_empty() {
+            // Walk again, now that it is known which blocks need a label.
+            analysis.labels = std::mem::take(&mut analysis.goto_targets);
+            analysis.reset();
+            analysis.walk_root(&loops);
+        }
+        let unstructured = std::mem::take(&mut analysis.unstructured);
+        let (pretty, handlers) = analysis.build(res);
+        Ok((pretty, unstructured, handlers))
     }
 }
 
+/// Decompiled event handler.
+pub(crate) struct HandlerScript {
+    /// Offset of the handler opcode.
+    pub(crate) pos: usize,
+    /// Tests of the enclosing conditionals, joined with `&&`.
+    pub(crate) condition: Option<DisRendered>,
+    pub(crate) script: DisRendered,
+}
+
+/// Number of steps the structurer may take on one object before giving up,
+/// and printing the rest with `goto`s.
+const MAX_WALK_STEPS: usize = 100_000;
+
+/// Blocks reachable from a block, with their distance, sorted by block.
+type Reachable = Rc<Vec<(BlockId, usize)>>;
+
 
 struct CfgAnalysis {
     #[allow(dead_code)]
     code_start: usize,
     blocks: HashMap<BlockId, Block>,
     output: AstStack,
-    //predoms: HashMap<BlockId, HashSet<BlockId>>,
-    postdoms: HashMap<BlockId, HashSet<BlockId>>,
-    //reach: HashMap<BlockId, HashSet<BlockId>>,
+    doms: DomTree,
+    postdoms: DomTree,
+    /// Blocks which are part of a natural loop, or `None` if the graph is
+    /// irreducible, and cycles may exist outside of natural loops.
+    loop_blocks: Option<HashSet<BlockId>>,
+    /// Results of `reachable`, which is called with the same arguments many
+    /// times when regions are duplicated.
+    reach: RefCell<HashMap<(BlockId, Vec<BlockId>), Reachable>>,
     path: Vec<BlockId>,
     path_loops: HashSet<BlockId>,
+    /// Ends of the regions being walked.
+    path_ends: Vec<BlockId>,
+    /// Blocks jumped to by a `goto` in an unstructured region.
+    goto_targets: HashSet<BlockId>,
+    /// Blocks to be preceded by a label.
+    labels: HashSet<BlockId>,
+    /// Why regions were printed with `goto`s.
+    unstructured: Vec<String>,
+    steps: usize,
 }
 
 impl CfgAnalysis {
@@ -75,15 +132,31 @@ impl CfgAnalysis {
                 current: Vec::new(),
                 parent: None,
             },
-            //predoms: HashMap::new(),
-            postdoms: HashMap::new(),
-            //reach: HashMap::new(),
+            doms: DomTree::default(),
+            postdoms: DomTree::default(),
+            loop_blocks: None,
+            reach: RefCell::new(HashMap::new()),
             path: Vec::new(),
             path_loops: HashSet::new(),
+            path_ends: Vec::new(),
+            goto_targets: HashSet::new(),
+            labels: HashSet::new(),
+            unstructured: Vec::new(),
+            steps: 0,
         }
     }
 
-    fn create_blocks(&mut self, mut info: Decompiler) {
+    /// Discards the output of a previous walk.
+    fn reset(&mut self) {
+        self.output = Default::default();
+        self.path.clear();
+        self.path_loops.clear();
+        self.path_ends.clear();
+        self.unstructured.clear();
+        self.steps = 0;
+    }
+
+    fn create_blocks(&mut self, info: &Decompiler) -> Result<(), DisError> {
         let mut pred = Vec::new();
         for start in info.block_starts.iter().copied() {
             let mut block = Block::new(start);
@@ -92,7 +165,7 @@ impl CfgAnalysis {
                     block.term = block.end;
                     break;
                 }
-                let (pos, ins) = DisIns::analyse_one(info.code, block.end).unwrap();
+                let (pos, ins) = DisIns::analyse_one(info.code, block.end, info.map)?;
                 if matches!(ins.op, DisOp::Exit) {
                     block.lines.push(AstToken::Exit(Some(block.end)));
                     block.term = block.end;
@@ -103,10 +176,12 @@ impl CfgAnalysis {
                 if matches!(ins.op, DisOp::Tick) {
                     block.lines.push(AstToken::Tick(Some(block.end)));
                 } else if !ins.op.is_terminator() && let Some(decomp) = info.pos_decomp.get(&block.end) {
-                    block.lines.push(AstToken::Line(Some(block.end), decomp.to_string()));
+                    for (idx, stmt) in decomp.iter().enumerate() {
+                        block.lines.push(AstToken::Line((idx == 0).then_some(block.end), stmt.clone()));
+                    }
                 }
-                if let Some(new_jumps) = info.pos_jump.remove(&block.end) {
-                    block.succ.extend(new_jumps.clone());
+                if let Some(new_jumps) = info.pos_jump.get(&block.end) {
+                    block.succ.extend(new_jumps.iter().cloned());
                 }
                 block.term = block.end;
                 block.end = pos;
@@ -135,91 +210,129 @@ impl CfgAnalysis {
                 continue;
             }
             self.blocks.get_mut(&edge.to)
-                .unwrap()
+                .ok_or_else(|| DisError::CfgAnalysisFailed(format!("no block at {:?}", edge.to)))?
                 .pred
                 .insert(pred.into());
         }
+        Ok(())
+    }
+
+    fn block(&self, id: BlockId) -> Result<&Block, DisError> {
+        self.blocks.get(&id).ok_or_else(|| DisError::CfgAnalysisFailed(format!("no block at {id:?}")))
     }
 
     fn compute_dominators(&mut self) {
-        fn compute(
-            keys: &HashSet<BlockId>,
-            doms: &mut HashMap<BlockId, HashSet<BlockId>>,
-            initial: BlockId,
-            last: BlockId,
-            pred: impl Fn(&BlockId) -> HashSet<BlockId>,
-        ) {
-            doms.insert(initial, HashSet::from([initial]));
-            for id in keys {
-                if *id == initial {
+        let succ = |id: BlockId| self.blocks.get(&id)
+            .map(|block| block.succ.iter().map(|e| e.to).collect())
+            .unwrap_or_default();
+        let pred = |id: BlockId| match id {
+            BlockId::End => self.blocks.iter()
+                .filter(|(_, block)| block.succ.iter().any(|e| e.to == BlockId::End))
+                .map(|(id, _)| *id)
+                .collect(),
+            _ => self.blocks.get(&id)
+                .map(|block| block.pred.iter().copied().collect())
+                .unwrap_or_default(),
+        };
+        self.doms = DomTree::new(0.into(), succ, pred);
+        self.postdoms = DomTree::new(BlockId::End, pred, succ);
+    }
+
+    /// Finds natural loops, i.e., the blocks which can reach the source of a
+    /// back edge (an edge to a block dominating its source) without passing
+    /// through its target, the loop header.
+    fn find_loops(&mut self) -> Result<(), DisError> {
+        let mut back_edges = HashSet::new();
+        let mut loop_blocks = HashSet::new();
+        for (id, block) in &self.blocks {
+            if !self.doms.contains(*id) {
+                continue;
+            }
+            for edge in &block.succ {
+                if edge.to == BlockId::End || !self.doms.dominates(edge.to, *id) {
                     continue;
                 }
-                doms.insert(*id, keys.clone());
-            }
-            let mut changed = true;
-            while changed {
-                changed = false;
-                for id in keys {
-                    if *id == initial || *id == last {
-                        continue;
+                back_edges.insert((*id, edge.to));
+                let mut body = HashSet::from([edge.to]);
+                let mut queue = vec![*id];
+                while let Some(id) = queue.pop() {
+                    if body.insert(id) {
+                        queue.extend(self.block(id)?.pred.iter().copied().filter(|pred| self.doms.contains(*pred)));
                     }
-                    let prev = doms.remove(id).unwrap();
-                    let mut new = pred(id)
-                        .iter()
-                        .map(|id| doms.get(id).unwrap().clone())
-                        .reduce(|a, b| a.intersection(&b).copied().collect())
-                        .unwrap_or_default();
-                    new.insert(*id);
-                    changed |= prev != new;
-                    doms.insert(*id, new);
                 }
+                loop_blocks.extend(body);
             }
         }
-        let mut keys = self.blocks.keys().copied().collect::<HashSet<BlockId>>();
-        keys.insert(BlockId::End);
-        //compute(&keys, &mut self.predoms, 0.into(), BlockId::End, |id| self.blocks.get(id).unwrap().pred.clone());
-        compute(&keys, &mut self.postdoms, BlockId::End, 0.into(), |id| self.blocks.get(id).unwrap().succ.iter()
-            //.filter(|e| !e.back_edge)
-            .map(|e| e.to).collect());
-        /*
-        for key in &keys {
-            let mut reachable = HashSet::new();
-            let mut queue = VecDeque::new();
-            queue.push_back(*key);
-            while let Some(id) = queue.pop_front() {
-                if reachable.contains(&id) {
-                    continue;
-                }
-                reachable.insert(id);
-                if id == BlockId::End {
-                    continue;
+
+        // Without back edges, the graph of a reducible CFG is acyclic.
+        let reachable = self.blocks.keys()
+            .copied()
+            .filter(|id| self.doms.contains(*id))
+            .collect::<Vec<_>>();
+        let forward_succ = |id: BlockId| Ok::<_, DisError>(self.block(id)?.succ.iter()
+            .map(|e| e.to)
+            .filter(|to| *to != BlockId::End && !back_edges.contains(&(id, *to)))
+            .collect::<HashSet<_>>());
+        let mut in_degree = reachable.iter()
+            .map(|id| (*id, 0))
+            .collect::<HashMap<_, _>>();
+        let unreachable = |to: BlockId| DisError::CfgAnalysisFailed(format!("{to:?} is not reachable"));
+        for id in &reachable {
+            for to in forward_succ(*id)? {
+                *in_degree.get_mut(&to).ok_or_else(|| unreachable(to))? += 1;
+            }
+        }
+        let mut queue = in_degree.iter()
+            .filter(|(_, degree)| **degree == 0)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        let mut sorted = 0;
+        while let Some(id) = queue.pop() {
+            sorted += 1;
+            for to in forward_succ(id)? {
+                let degree = in_degree.get_mut(&to).ok_or_else(|| unreachable(to))?;
+                *degree -= 1;
+                if *degree == 0 {
+                    queue.push(to);
                 }
-                queue.extend(self.blocks.get(&id).unwrap().succ.iter().map(|e| e.to).filter(|nid| !reachable.contains(nid)));
             }
-            self.reach.insert(*key, reachable);
         }
-        */
+        self.loop_blocks = (sorted == reachable.len()).then_some(loop_blocks);
+        Ok(())
     }
 
     fn find_join_point(
         &self,
         cond_id: BlockId,
         end: BlockId,
-    ) -> BlockId {
-        let block = self.blocks.get(&cond_id).unwrap();
-        let next = block.succ.to_vec();
-        self.reachable(cond_id, &[end].into())
-            .into_iter()
-            .filter(|(id, _)| next.iter().all(|e| self.postdoms.get(&e.to).unwrap().contains(id)))
+    ) -> Result<Option<BlockId>, DisError> {
+        let block = self.block(cond_id)?;
+        // Branches back to the head of an enclosing loop become `continue`,
+        // so they do not need to join.
+        let next = block.succ.iter()
+            .map(|e| e.to)
+            .filter(|id| !self.path_loops.contains(id))
+            .collect::<Vec<_>>();
+        Ok(self.reachable(cond_id, &[end].into())?
+            .iter()
+            .filter(|(id, _)| next.iter().all(|to| self.postdoms.dominates(*id, *to)))
             .min_by_key(|(_, dist)| *dist)
-            .unwrap().0
+            .map(|(id, _)| *id))
     }
 
+    /// Finds the blocks reachable from `start` (in at least one step), with
+    /// their distance. Blocks in `ends` are not passed through.
     fn reachable(
         &self,
         start: BlockId,
         ends: &HashSet<BlockId>,
-    ) -> Vec<(BlockId, usize)> {
+    ) -> Result<Reachable, DisError> {
+        let mut key = (start, ends.iter().copied().collect::<Vec<_>>());
+        key.1.sort();
+        if let Some(result) = self.reach.borrow().get(&key) {
+            return Ok(result.clone());
+        }
+
         let mut reachable = HashMap::new();
         let mut queue = VecDeque::new();
         queue.push_back((start, 0));
@@ -235,8 +348,7 @@ impl CfgAnalysis {
             if id == BlockId::End || ends.contains(&id) {
                 continue;
             }
-            queue.extend(self.blocks.get(&id)
-                .unwrap()
+            queue.extend(self.block(id)?
                 .succ
                 .iter()
                 .map(|e| (e.to, dist + 1))
@@ -248,89 +360,192 @@ impl CfgAnalysis {
         }
         let mut result = reachable.into_iter().collect::<Vec<_>>();
         result.sort();
+        let result = Rc::new(result);
+        self.reach.borrow_mut().insert(key, result.clone());
+        Ok(result)
+    }
+
+    /// Finds the blocks from which `target` can be reached (in at least one
+    /// step), out of the blocks in `within`. Blocks in `stops` are not passed
+    /// through.
+    fn reaching(
+        &self,
+        target: BlockId,
+        within: &HashSet<BlockId>,
+        stops: &HashSet<BlockId>,
+    ) -> HashSet<BlockId> {
+        let mut result = HashSet::new();
+        let mut queue = vec![target];
+        while let Some(id) = queue.pop() {
+            let Some(block) = self.blocks.get(&id) else { continue; };
+            for pred in &block.pred {
+                if within.contains(pred) && !stops.contains(pred) && result.insert(*pred) {
+                    queue.push(*pred);
+                }
+            }
+        }
         result
     }
 
+    /// Checks whether `loop_id` is the head of a loop within the region
+    /// ending at `end`, and returns where the loop is exited to. Exits from
+    /// the head (`while` loops) are preferred over exits from a block jumping
+    /// back to the head (`do`-`while` loops).
     fn find_loop_end(
         &self,
         loop_id: BlockId,
         end: BlockId,
-    ) -> Option<BlockId> {
+    ) -> Result<Option<BlockId>, DisError> {
+        if self.loop_blocks.as_ref().is_some_and(|blocks| !blocks.contains(&loop_id)) {
+            return Ok(None);
+        }
         let mut backedges: HashSet<BlockId> = [end].into();
-        backedges.extend(self.path_loops.clone());
-        self.reachable(loop_id, &backedges)
-            .into_iter()
-            .filter_map(|(id, dist)| {
-                if id == BlockId::End { return None; }
-                if backedges.contains(&id) { return None; }
-                if dist == 0 { return None; }
-                let block = self.blocks.get(&id).unwrap();
-                if id != loop_id && block.succ.len() != 1 { return None; }
-                if !self.reachable(id, &backedges).into_iter().any(|(r, _)| r == loop_id) { return None; }
-                for next in &block.succ {
-                    if next.to == BlockId::End { continue; }
-                    if backedges.contains(&next.to) { continue; }
-                    if self.reachable(next.to, &backedges).into_iter().any(|(r, _)| r == loop_id) { continue; }
-                    return Some((next.to, dist));
+        backedges.extend(self.path_loops.iter().copied());
+        // Only the blocks reachable from the head can be on a path back to it.
+        let mut within = self.reachable(loop_id, &backedges)?
+            .iter()
+            .map(|(id, _)| *id)
+            .collect::<HashSet<_>>();
+        within.insert(loop_id);
+        let reaching = self.reaching(loop_id, &within, &backedges);
+        if !reaching.contains(&loop_id) {
+            return Ok(None);
+        }
+        let exit = |id: &BlockId| self.blocks.get(id)?
+            .succ
+            .iter()
+            .map(|e| e.to)
+            .find(|next| *next != BlockId::End && !backedges.contains(next) && !reaching.contains(next));
+        if let Some(loop_end) = exit(&loop_id) {
+            return Ok(Some(loop_end));
+        }
+        let mut latches = self.block(loop_id)?
+            .pred
+            .iter()
+            .filter(|id| reaching.contains(id))
+            .copied()
+            .collect::<Vec<_>>();
+        latches.sort();
+        Ok(latches.iter().rev().find_map(|latch| {
+            // The condition may be tested just before the block jumping back.
+            let mut id = *latch;
+            loop {
+                if let Some(loop_end) = exit(&id) {
+                    return Some(loop_end);
                 }
-                None
-            })
-            .min_by_key(|(_, dist)| *dist)
-            .map(|(id, _)| id)
+                let block = self.blocks.get(&id)?;
+                if block.succ.len() != 1 || block.pred.len() != 1 {
+                    return None;
+                }
+                id = block.pred.iter().copied().next()?;
+                if id == loop_id || !reaching.contains(&id) {
+                    return None;
+                }
+            }
+        }))
     }
 
     fn output(&mut self) -> &mut Vec<AstToken> {
         &mut self.output.current
     }
 
+    /// Outputs the whole code. Without its loops, no region can be
+    /// structured, so it is all output with `goto`s.
+    fn walk_root(&mut self, loops: &Result<(), DisError>) {
+        match loops {
+            Ok(()) => self.walk(0.into(), BlockId::End),
+            Err(err) => {
+                self.unstructured.push(format!("{:?}: {err:?}", BlockId::from(0)));
+                self.walk_unstructured(0.into(), BlockId::End);
+            }
+        }
+    }
+
+    /// Outputs the region from `start` up to (excluding) `end`. Regions which
+    /// cannot be structured are output with `goto`s instead.
     fn walk(
         &mut self,
         start: BlockId,
         end: BlockId,
-    ) -> Result<(), DisError> {
+    ) {
         if start == end || start == BlockId::End {
-            return Ok(());
+            return;
         }
 
         if self.path.contains(&start) {
             self.output().push(AstToken::Continue);
-            return Ok(());
+            return;
+        }
+
+        self.path_ends.push(end);
+        if let Err(err) = self.walk_structured(start, end) {
+            self.unstructured.push(format!("{start:?}: {err:?}"));
+            self.walk_unstructured(start, end);
         }
+        self.path_ends.pop();
+    }
 
-        let block = self.blocks.get(&start).unwrap();
-        if !self.path_loops.contains(&start) && let Some(loop_end) = self.find_loop_end(start, end) {
+    /// Structures the region from `start`. Fails without producing output if
+    /// the region cannot be structured.
+    fn walk_structured(
+        &mut self,
+        start: BlockId,
+        end: BlockId,
+    ) -> Result<(), DisError> {
+        self.steps += 1;
+        if self.steps > MAX_WALK_STEPS {
+            return Err(DisError::CfgAnalysisFailed("too many steps".to_string()));
+        }
+
+        let block = self.blocks.get(&start)
+            .ok_or_else(|| DisError::CfgAnalysisFailed(format!("no block at {start:?}")))?;
+        if !self.path_loops.contains(&start) && let Some(loop_end) = self.find_loop_end(start, end)? {
             let prev_output = std::mem::take(&mut self.output);
             self.output = AstStack {
                 current: Vec::new(),
                 parent: Some(Box::new(prev_output)),
             };
             self.path_loops.insert(start);
-            self.walk(start, loop_end)?;
+            self.walk(start, loop_end);
 
             let mut block_content;
             (block_content, self.output) = match std::mem::take(&mut self.output) {
                 AstStack { current, parent: Some(parent) } => (current, *parent),
                 _ => unreachable!(),
             };
+            if !self.path_loops.remove(&start) {
+                return Err(DisError::CfgAnalysisFailed(format!("loop at {start:?} left twice")));
+            }
             block_content.push(AstToken::Break);
-            self.output().push(ast::make_loop(block_content));
-            assert!(self.path_loops.remove(&start));
+            ast::make_loop(self.output(), block_content);
 
-            self.walk(loop_end, end)?;
+            self.walk(loop_end, end);
             return Ok(());
         }
 
+        let next = block.succ.to_vec();
+        if next.len() > 2 {
+            return Err(DisError::CfgAnalysisFailed(format!("{} successors of {start:?}", next.len())));
+        }
+        let join = if block.exit {
+            end
+        } else {
+            self.find_join_point(start, end)?
+                .ok_or_else(|| DisError::CfgAnalysisFailed(format!("no join point for {start:?}")))?
+        };
+
+        let (path_len, output_len) = (self.path.len(), self.output.current.len());
         self.path.push(start);
+        if self.labels.contains(&start) && let BlockId::Block(addr) = start {
+            self.output.current.push(AstToken::Label(addr));
+        }
         for line in &block.lines {
             self.output.current.push(line.clone());
         }
-        let next = block.succ.to_vec();
-        assert!(next.len() <= 2);
-        let join = self.find_join_point(start, end);
         if !block.exit {
             if !next.is_empty() {
                 if next.len() == 1 && matches!(next[0].kind, DisJump::Straight | DisJump::Unconditional) {
-                    self.walk(next[0].to, join)?;
+                    self.walk(next[0].to, join);
                 } else {
                     let mut output_branches = Vec::new();
                     for (branch, edge) in next.into_iter().enumerate() {
@@ -339,7 +554,7 @@ impl CfgAnalysis {
                             current: Vec::new(),
                             parent: Some(Box::new(prev_output)),
                         };
-                        self.walk(edge.to, join)?;
+                        self.walk(edge.to, join);
                         let block_content;
                         (block_content, self.output) = match std::mem::take(&mut self.output) {
                             AstStack { current, parent: Some(parent) } => (current, *parent),
@@ -350,7 +565,7 @@ impl CfgAnalysis {
                         }
                         output_branches.push((
                             edge.line,
-                            edge.kind.show_decomp(),
+                            edge.kind.clone(),
                             edge.kind.is_fallthrough(),
                             block_content,
                         ));
@@ -358,16 +573,132 @@ impl CfgAnalysis {
                     self.output().extend(ast::make_chain(output_branches));
                 }
             }
-            self.walk(join, end)?;
+            self.walk(join, end);
+        }
+        if self.path.pop() != Some(start) {
+            self.path.truncate(path_len);
+            self.output.current.truncate(output_len);
+            return Err(DisError::CfgAnalysisFailed(format!("path changed while walking {start:?}")));
         }
-        assert_eq!(self.path.pop().unwrap(), start);
         Ok(())
     }
 
-    fn build(self) -> String {
+    /// Outputs the blocks of the region from `start` in address order, each
+    /// followed by explicit `goto`s to its successors.
+    fn walk_unstructured(
+        &mut self,
+        start: BlockId,
+        end: BlockId,
+    ) {
+        let mut stops = self.path.iter().copied().collect::<HashSet<_>>();
+        stops.extend(self.path_loops.iter().copied());
+        stops.extend(self.path_ends.iter().copied());
+        stops.remove(&start);
+        // Blocks which cannot be walked are left out, like those outside of
+        // the region.
+        let mut region = self.reachable(start, &stops)
+            .unwrap_or_default()
+            .iter()
+            .map(|(id, _)| *id)
+            .filter(|id| *id != BlockId::End && !stops.contains(id) && self.blocks.contains_key(id))
+            .collect::<Vec<_>>();
+        if self.blocks.contains_key(&start) {
+            region.push(start);
+        }
+        region.sort();
+        region.dedup();
+
+        for (idx, id) in region.iter().enumerate() {
+            let Some(block) = self.blocks.get(id) else { continue; };
+            if let BlockId::Block(addr) = id && self.labels.contains(id) {
+                self.output.current.push(AstToken::Label(*addr));
+            }
+            self.output.current.extend(block.lines.iter().cloned());
+            // The region is followed by `end`.
+            let next = region.get(idx + 1).copied().unwrap_or(end);
+            let mut gotos = Vec::new();
+            let mut otherwise = None;
+            for edge in &block.succ {
+                let BlockId::Block(target) = edge.to else { continue; };
+                if matches!(edge.kind, DisJump::Straight | DisJump::Unconditional) || edge.kind.is_fallthrough() {
+                    otherwise = Some((edge.line, edge.to, target));
+                } else {
+                    self.goto_targets.insert(edge.to);
+                    gotos.push(AstToken::Goto(edge.line, Some(edge.kind.clone()), target));
+                }
+            }
+            if let Some((line, to, target)) = otherwise && to != next {
+                self.goto_targets.insert(to);
+                gotos.push(AstToken::Goto(if gotos.is_empty() { line } else { None }, None, target));
+            }
+            self.output.current.extend(gotos);
+        }
+    }
+
+    fn build(self, res: Resources) -> (DisRendered, Vec<HandlerScript>) {
         assert!(self.output.parent.is_none());
-        let mut output = String::new();
-        ast::build(self.code_start, &self.output.current, &mut output, 0, &mut 0);
-        output
+        // Blocks are numbered for the anchors of the HTML page only, so they
+        // are counted on across the handlers to stay unique on it.
+        let mut block_counter = 0;
+        let mut render = |ast: &[AstToken]| {
+            let mut html = String::new();
+            ast::build(self.code_start, &Html(res), ast, &mut html, 0, &mut block_counter);
+            let mut text = String::new();
+            ast::build(self.code_start, &Text(Some(res.opcodes)), ast, &mut text, 0, &mut 0);
+            DisRendered { html, text }
+        };
+        let output = render(&self.output.current);
+
+        let mut found = Vec::new();
+        ast::find_handlers(&self.output.current, &mut Vec::new(), &mut found);
+        let handlers = found.into_iter()
+            .map(|handler| HandlerScript {
+                pos: handler.pos,
+                condition: handler.conditions.into_iter()
+                    .reduce(|lhs, rhs| Expr::binop(BinOp::LogicAnd, lhs, rhs))
+                    .map(|condition| DisRendered {
+                        html: condition.html(res),
+                        text: condition.text(res),
+                    }),
+                script: render(handler.body),
+            })
+            .collect();
+        (output, handlers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Analysis of a single block jumping to an offset where no block starts,
+    /// as `create_blocks` would never produce.
+    fn dangling_jump() -> CfgAnalysis {
+        let mut analysis = CfgAnalysis::new(0x18);
+        let mut block = Block::new(0);
+        block.succ.push(BlockEdge {
+            line: None,
+            to: 4.into(),
+            kind: DisJump::Unconditional,
+        });
+        analysis.blocks.insert(0.into(), block);
+        analysis.compute_dominators();
+        analysis
+    }
+
+    #[test]
+    fn missing_block_is_an_error() {
+        let mut analysis = dangling_jump();
+        assert!(analysis.reachable(0.into(), &HashSet::new()).is_err());
+        assert!(analysis.find_loops().is_err());
+    }
+
+    #[test]
+    fn missing_block_falls_back_to_gotos() {
+        let mut analysis = dangling_jump();
+        let loops = analysis.find_loops();
+        analysis.walk_root(&loops);
+        assert_eq!(analysis.unstructured.len(), 1);
+        assert!(matches!(analysis.output.current[..], [AstToken::Goto(_, None, 4)]));
     }
 }
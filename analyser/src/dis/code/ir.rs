@@ -0,0 +1,576 @@
+use serde::Serialize;
+
+use crate::{dis::{htmlsan, show_string}, Resources, SCB, SDB, SE};
+
+use super::{opdb::{OpcodeDb, OpcodeInfo}, show_addr, DisJump};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum UnOp {
+    Neg,
+    BitNot,
+    Not,
+    /// Integer to string conversion, used for clone names.
+    ToStr,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum BinOp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    BitAnd,
+    BitOr,
+    Xor,
+    Shl,
+    Shr,
+    LogicAnd,
+    LogicOr,
+    /// String comparison.
+    StrEq,
+    /// String concatenation, used for clone names.
+    Concat,
+}
+
+impl BinOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Gt => ">",
+            Self::Le => "<=",
+            Self::Ge => ">=",
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Mod => "%",
+            Self::BitAnd => "&",
+            Self::BitOr => "|",
+            Self::Xor => "^",
+            Self::Shl => "<<",
+            Self::Shr => ">>",
+            Self::LogicAnd => "&&",
+            Self::LogicOr => "||",
+            Self::StrEq => "==s",
+            Self::Concat => "+s",
+        }
+    }
+
+    /// Evaluates the operator on constant integer operands.
+    pub fn apply(&self, lhs: u32, rhs: u32) -> Option<u32> {
+        Some(match self {
+            Self::Eq => (lhs == rhs) as u32,
+            Self::Ne => (lhs != rhs) as u32,
+            Self::Lt => (lhs < rhs) as u32,
+            Self::Gt => (lhs > rhs) as u32,
+            Self::Le => (lhs <= rhs) as u32,
+            Self::Ge => (lhs >= rhs) as u32,
+            Self::Add => lhs.wrapping_add(rhs),
+            Self::Sub => lhs.wrapping_sub(rhs),
+            Self::Mul => lhs.wrapping_mul(rhs),
+            Self::Div => lhs.checked_div(rhs)?,
+            Self::Mod => lhs.checked_rem(rhs)?,
+            Self::BitAnd => lhs & rhs,
+            Self::BitOr => lhs | rhs,
+            Self::Xor => lhs ^ rhs,
+            Self::Shl => lhs.wrapping_shl(rhs),
+            Self::Shr => lhs.wrapping_shr(rhs),
+            Self::LogicAnd => (lhs != 0 && rhs != 0) as u32,
+            Self::LogicOr => (lhs != 0 || rhs != 0) as u32,
+            Self::StrEq | Self::Concat => return None,
+        })
+    }
+
+    /// The comparison with the opposite result, if this is a comparison.
+    pub fn negated(&self) -> Option<Self> {
+        Some(match self {
+            Self::Eq => Self::Ne,
+            Self::Ne => Self::Eq,
+            Self::Lt => Self::Ge,
+            Self::Gt => Self::Le,
+            Self::Le => Self::Gt,
+            Self::Ge => Self::Lt,
+            _ => return None,
+        })
+    }
+
+    /// Binding strength in the script dialect, from 0 (`||`) to 9 (`*`).
+    /// Must match the levels of `script::BINOPS`.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Self::LogicOr => 0,
+            Self::LogicAnd => 1,
+            Self::BitOr => 2,
+            Self::Xor => 3,
+            Self::BitAnd => 4,
+            Self::Eq | Self::Ne | Self::StrEq => 5,
+            Self::Lt | Self::Gt | Self::Le | Self::Ge => 6,
+            Self::Shl | Self::Shr => 7,
+            Self::Add | Self::Sub | Self::Concat => 8,
+            Self::Mul | Self::Div | Self::Mod => 9,
+        }
+    }
+}
+
+/// Binding strength of unary operators, above all binary operators.
+const UNARY_PRECEDENCE: u8 = 10;
+
+/// Binding strength of expressions which never need parentheses.
+const ATOM_PRECEDENCE: u8 = 11;
+
+/// Expression, as produced by the opcode semantics.
+///
+/// Values on the stack are expressions too. Constants pushed by the code are
+/// `Const` until they are used: depending on the opcode, they are then either
+/// integers or indices into the string pool.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize)]
+pub enum Expr {
+    /// Constant of unknown type.
+    Const(u32),
+    Int(u32),
+    Str(String),
+    /// String pool index which is out of range.
+    BadStr(u32),
+    /// Engine state or function, e.g. `mouse`, `random`.
+    Dyn(&'static str),
+    /// Plain name or keyword, e.g. `default`.
+    Name(&'static str),
+    /// Global variable.
+    Global(Box<Expr>),
+    /// Indexed engine state, e.g. `char["x"]`.
+    Index(&'static str, Box<Expr>),
+    Field(Box<Expr>, &'static str),
+    /// Call with optionally labelled arguments.
+    Call(Box<Expr>, Vec<(Option<&'static str>, Expr)>),
+    Tuple(Vec<(Option<&'static str>, Expr)>),
+    Unop(UnOp, Box<Expr>),
+    Binop(BinOp, Box<Expr>, Box<Expr>),
+    /// Integer with its meaning for a particular global, if known.
+    Hint(Box<Expr>, Option<String>),
+    /// Position in the string FIFO.
+    Fifo(usize),
+    #[default]
+    Unknown,
+}
+
+impl Expr {
+    pub fn field(self, name: &'static str) -> Self {
+        Self::Field(Box::new(self), name)
+    }
+
+    pub fn call(self, args: Vec<Expr>) -> Self {
+        Self::Call(Box::new(self), args.into_iter().map(|arg| (None, arg)).collect())
+    }
+
+    pub fn call_labelled(self, args: Vec<(&'static str, Expr)>) -> Self {
+        Self::Call(Box::new(self), args.into_iter().map(|(label, arg)| (Some(label), arg)).collect())
+    }
+
+    pub fn index(base: &'static str, index: Expr) -> Self {
+        Self::Index(base, Box::new(index))
+    }
+
+    pub fn global(name: Expr) -> Self {
+        Self::Global(Box::new(name))
+    }
+
+    pub fn unop(op: UnOp, val: Expr) -> Self {
+        Self::Unop(op, Box::new(val))
+    }
+
+    pub fn binop(op: BinOp, lhs: Expr, rhs: Expr) -> Self {
+        Self::Binop(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn as_int(&self) -> Option<u32> {
+        match self {
+            Self::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Folds a negated comparison, `!(a == b)` or `(a == b) == 0`, into the
+    /// opposite comparison, `a != b`.
+    pub fn fold_negation(&self) -> Option<Self> {
+        match self {
+            Self::Unop(UnOp::Not, box Self::Binop(op, lhs, rhs))
+            | Self::Binop(BinOp::Eq, box Self::Binop(op, lhs, rhs), box (Self::Const(0) | Self::Int(0)))
+            | Self::Binop(BinOp::Eq, box (Self::Const(0) | Self::Int(0)), box Self::Binop(op, lhs, rhs)) => {
+                Some(Self::Binop(op.negated()?, lhs.clone(), rhs.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Binop(op, ..) => op.precedence(),
+            Self::Unop(UnOp::ToStr, _) => ATOM_PRECEDENCE,
+            Self::Unop(..) => UNARY_PRECEDENCE,
+            Self::Hint(val, _) => val.precedence(),
+            _ => ATOM_PRECEDENCE,
+        }
+    }
+
+    pub fn html(&self, res: Resources) -> String {
+        let mut output = String::new();
+        write_expr(&mut output, self, &Html(res));
+        output
+    }
+
+    /// Renders the expression as plain text, with the labels of unknown
+    /// opcodes.
+    pub fn text(&self, res: Resources) -> String {
+        let mut output = String::new();
+        write_expr(&mut output, self, &Text(Some(res.opcodes)));
+        output
+    }
+}
+
+/// Decompiled statement. Each opcode produces zero or more statements.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub enum Stmt {
+    /// Assignment, e.g. `global["x"] = 1`, `global["x"] += 1`, `var["x"] := 1`.
+    Assign(Expr, &'static str, Expr),
+    /// Increment or decrement, either prefix (`++global["x"]`) or postfix.
+    Update(&'static str, bool, Expr),
+    /// Expression evaluated for its effect, e.g. a call.
+    Expr(Expr),
+    /// Opcode with several parameters, shown as a list.
+    Record(&'static str, Vec<(&'static str, Expr)>),
+    /// Opcode with unknown semantics, with an optional guess.
+    Unknown(&'static str, Option<&'static str>, Vec<Expr>),
+    /// Statement which also pushes a value.
+    Push(Box<Stmt>),
+    /// Jump to the given address. The kind is that of the fallthrough edge,
+    /// except for unknown jumps.
+    Jump(DisJump, usize),
+    Exit,
+    Quit,
+    Tick,
+}
+
+impl Stmt {
+    pub fn html(&self, res: Resources) -> String {
+        let mut output = String::new();
+        write_stmt(&mut output, self, &Html(res));
+        output
+    }
+}
+
+impl DisJump {
+    pub fn html(&self, res: Resources) -> String {
+        let mut output = String::new();
+        write_jump(&mut output, self, &Html(res));
+        output
+    }
+
+    pub fn text(&self, res: Resources) -> String {
+        let mut output = String::new();
+        write_jump(&mut output, self, &Text(Some(res.opcodes)));
+        output
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut output = String::new();
+        write_expr(&mut output, self, &Text(None));
+        f.write_str(&output)
+    }
+}
+
+impl std::fmt::Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut output = String::new();
+        write_stmt(&mut output, self, &Text(None));
+        f.write_str(&output)
+    }
+}
+
+impl std::fmt::Display for DisJump {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut output = String::new();
+        write_jump(&mut output, self, &Text(None));
+        f.write_str(&output)
+    }
+}
+
+/// Output format of the renderer.
+pub(super) trait Style {
+    fn text(&self, s: &str) -> String;
+    fn dynamic(&self, name: &str) -> String;
+    fn string(&self, s: &str) -> String;
+    fn comment(&self, s: &str) -> String;
+    fn keyword(&self, s: &str) -> String;
+    fn addr(&self, pos: usize) -> String;
+    /// Runtime labels of an unknown opcode, by its placeholder name.
+    fn opcode(&self, name: &str) -> Option<&OpcodeInfo>;
+    /// Opening and closing brace of the block numbered `id` in the script.
+    fn open_block(&self, id: usize) -> String;
+    fn close_block(&self, id: usize) -> String;
+    /// Label of the statement at `addr`, where it is defined and where a
+    /// `goto` refers to it.
+    fn label(&self, addr: usize) -> String;
+    fn label_ref(&self, addr: usize) -> String;
+}
+
+/// Plain text, with the labels of unknown opcodes from the database, if given.
+pub(super) struct Text<'a>(pub(super) Option<&'a OpcodeDb>);
+
+impl<'a> Style for Text<'a> {
+    fn text(&self, s: &str) -> String { s.to_string() }
+    fn dynamic(&self, name: &str) -> String { name.to_string() }
+    fn string(&self, s: &str) -> String { format!("\"{}\"", crate::asm::escape_string(s)) }
+    fn comment(&self, s: &str) -> String { s.to_string() }
+    fn keyword(&self, s: &str) -> String { s.to_string() }
+    fn addr(&self, pos: usize) -> String { format!("{pos:04x}") }
+    fn opcode(&self, name: &str) -> Option<&OpcodeInfo> { self.0?.get_by_name(name) }
+    fn open_block(&self, _id: usize) -> String { "{".to_string() }
+    fn close_block(&self, _id: usize) -> String { "}".to_string() }
+    fn label(&self, addr: usize) -> String { format!("L_{addr:04x}") }
+    fn label_ref(&self, addr: usize) -> String { format!("L_{addr:04x}") }
+}
+
+pub(super) struct Html<'a>(pub(super) Resources<'a>);
+
+impl<'a> Style for Html<'a> {
+    fn text(&self, s: &str) -> String { htmlsan(s) }
+    fn dynamic(&self, name: &str) -> String { format!("{SDB}{name}{SE}") }
+    fn string(&self, s: &str) -> String { show_string(s, self.0) }
+    fn comment(&self, s: &str) -> String { format!("{SCB}{}{SE}", htmlsan(s)) }
+    fn keyword(&self, s: &str) -> String { format!("<span class=\"hl-kw\">{s}</span>") }
+    fn addr(&self, pos: usize) -> String { show_addr(pos) }
+    fn opcode(&self, name: &str) -> Option<&OpcodeInfo> { self.0.opcodes.get_by_name(name) }
+    fn open_block(&self, id: usize) -> String { format!("<a href=\"#be-{id}\" id=\"bb-{id}\">{{</a>") }
+    fn close_block(&self, id: usize) -> String { format!("<a href=\"#bb-{id}\" id=\"be-{id}\">}}</a>") }
+    fn label(&self, addr: usize) -> String { format!("<span id=\"lbl-{addr:04x}\">L_{addr:04x}</span>") }
+    fn label_ref(&self, addr: usize) -> String { format!("<a href=\"#lbl-{addr:04x}\">L_{addr:04x}</a>") }
+}
+
+fn write_args(output: &mut String, args: &[(Option<&'static str>, Expr)], style: &dyn Style) {
+    for (idx, (label, arg)) in args.iter().enumerate() {
+        if idx > 0 {
+            output.push_str(", ");
+        }
+        if let Some(label) = label {
+            output.push_str(label);
+            output.push_str(": ");
+        }
+        write_expr(output, arg, style);
+    }
+}
+
+/// Writes an operand, with parentheses if it binds less strongly than
+/// `min_precedence`.
+fn write_operand(output: &mut String, expr: &Expr, style: &dyn Style, min_precedence: u8) {
+    let folded = expr.fold_negation();
+    let expr = folded.as_ref().unwrap_or(expr);
+    if expr.precedence() < min_precedence {
+        output.push('(');
+        write_expr(output, expr, style);
+        output.push(')');
+    } else {
+        write_expr(output, expr, style);
+    }
+}
+
+pub(super) fn write_expr(output: &mut String, expr: &Expr, style: &dyn Style) {
+    if let Some(folded) = expr.fold_negation() {
+        return write_expr(output, &folded, style);
+    }
+    match expr {
+        Expr::Const(value) | Expr::Int(value) => output.push_str(&value.to_string()),
+        Expr::Str(s) => output.push_str(&style.string(s)),
+        Expr::BadStr(idx) => output.push_str(&format!("const:{idx}")),
+        Expr::Dyn(name) => output.push_str(&style.dynamic(name)),
+        Expr::Name(name) => output.push_str(name),
+        Expr::Global(name) => {
+            output.push_str(&style.dynamic("global"));
+            output.push('[');
+            write_expr(output, name, style);
+            output.push(']');
+        }
+        Expr::Index(base, index) => {
+            output.push_str(&style.dynamic(base));
+            output.push('[');
+            write_expr(output, index, style);
+            output.push(']');
+        }
+        Expr::Field(base, name) => {
+            write_expr(output, base, style);
+            output.push('.');
+            output.push_str(name);
+        }
+        Expr::Call(callee, args) => {
+            write_expr(output, callee, style);
+            output.push('(');
+            write_args(output, args, style);
+            output.push(')');
+        }
+        Expr::Tuple(args) => {
+            output.push('(');
+            write_args(output, args, style);
+            output.push(')');
+        }
+        Expr::Unop(UnOp::ToStr, val) => {
+            output.push_str("str(");
+            write_expr(output, val, style);
+            output.push(')');
+        }
+        Expr::Unop(op, val) => {
+            output.push_str(match op {
+                UnOp::Neg => "-",
+                UnOp::BitNot => "~",
+                _ => "!",
+            });
+            // Nested unary operators are parenthesised, so that e.g. `- -x`
+            // is not printed as a decrement.
+            write_operand(output, val, style, UNARY_PRECEDENCE + 1);
+        }
+        Expr::Binop(op, lhs, rhs) => {
+            // Operators are left-associative, so the right operand needs
+            // parentheses on the same level.
+            write_operand(output, lhs, style, op.precedence());
+            output.push(' ');
+            output.push_str(&style.text(op.symbol()));
+            output.push(' ');
+            write_operand(output, rhs, style, op.precedence() + 1);
+        }
+        Expr::Hint(val, hint) => {
+            write_expr(output, val, style);
+            output.push(' ');
+            output.push_str(&style.comment(&format!("({})", hint.as_deref().unwrap_or("?"))));
+        }
+        Expr::Fifo(idx) => output.push_str(&format!("fifo{idx}")),
+        Expr::Unknown => output.push('?'),
+    }
+}
+
+pub(super) fn write_stmt(output: &mut String, stmt: &Stmt, style: &dyn Style) {
+    match stmt {
+        Stmt::Assign(lhs, op, rhs) => {
+            write_expr(output, lhs, style);
+            output.push_str(&format!(" {op} "));
+            write_expr(output, rhs, style);
+        }
+        Stmt::Update(op, true, val) => {
+            output.push_str(op);
+            write_expr(output, val, style);
+        }
+        Stmt::Update(op, false, val) => {
+            write_expr(output, val, style);
+            output.push_str(op);
+        }
+        Stmt::Expr(expr) => write_expr(output, expr, style),
+        Stmt::Record(title, fields) => {
+            output.push_str(title);
+            let width = fields.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+            for (name, value) in fields {
+                output.push_str(&format!("\n- {:width$} ", format!("{name}:"), width = width + 1));
+                write_expr(output, value, style);
+            }
+        }
+        Stmt::Unknown(name, note, args) => {
+            let info = style.opcode(name);
+            output.push_str(&style.text(info.and_then(|info| info.name.as_deref()).unwrap_or(name)));
+            output.push('(');
+            if let Some(note) = info.and_then(|info| info.description.as_deref()).or(*note) {
+                output.push_str(&style.text(note));
+                if !args.is_empty() {
+                    output.push(' ');
+                }
+            }
+            for (idx, arg) in args.iter().enumerate() {
+                if idx > 0 {
+                    output.push_str(", ");
+                }
+                if let Some(label) = info.and_then(|info| info.args.get(idx)) {
+                    output.push_str(&style.text(label));
+                    output.push_str(": ");
+                }
+                write_expr(output, arg, style);
+            }
+            output.push(')');
+        }
+        Stmt::Push(stmt) => {
+            output.push_str("push(");
+            write_stmt(output, stmt, style);
+            output.push(')');
+        }
+        Stmt::Jump(DisJump::Unconditional, target) => {
+            output.push_str(&format!("goto {}", style.addr(*target)));
+        }
+        Stmt::Jump(DisJump::Unknown { op, arg }, target) => {
+            output.push_str(&format!("unknown goto 0x{op:02X}"));
+            if let Some(arg) = arg {
+                output.push('(');
+                write_expr(output, arg, style);
+                output.push(')');
+            }
+            output.push_str(&format!(" {}", style.addr(*target)));
+        }
+        Stmt::Jump(kind, target) => {
+            write_jump(output, kind, style);
+            output.push_str(&format!(" else goto {}", style.addr(*target)));
+        }
+        Stmt::Exit => output.push_str(&style.keyword("exit")),
+        Stmt::Quit => output.push_str(&style.keyword("quit")),
+        Stmt::Tick => output.push_str(&style.keyword("tick")),
+    }
+}
+
+pub(super) fn write_jump(output: &mut String, kind: &DisJump, style: &dyn Style) {
+    match kind {
+        DisJump::Unconditional => output.push_str("unconditional"),
+        DisJump::Conditional { test } => {
+            output.push_str("if (");
+            write_expr(output, test, style);
+            output.push(')');
+        }
+        DisJump::Unknown { op, arg: None } => output.push_str(&format!("unknown(0x{op:02x})")),
+        DisJump::Unknown { op, arg: Some(arg) } => {
+            output.push_str(&format!("unknown(0x{op:02x}, "));
+            write_expr(output, arg, style);
+            output.push(')');
+        }
+        DisJump::OnInit => output.push_str("on init"),
+        DisJump::OnInteract(false) => output.push_str("on interact (LMB)"),
+        DisJump::OnInteract(true) => output.push_str("on interact (RMB)"),
+        DisJump::OnKey { key } => {
+            output.push_str("on key(");
+            write_expr(output, key, style);
+            output.push(')');
+        }
+        DisJump::OnCombine { with } => {
+            output.push_str("on combine(");
+            write_expr(output, with, style);
+            output.push(')');
+        }
+
+        DisJump::Straight => output.push_str("straightline"),
+
+        DisJump::ConditionalFallthrough => output.push_str("conditional fallthrough"),
+        DisJump::UnknownFallthrough => output.push_str("unknown fallthrough"),
+        DisJump::OnInitFallthrough => output.push_str("on init fallthrough"),
+        DisJump::OnInteractFallthrough => output.push_str("on interact fallthrough"),
+        DisJump::OnKeyFallthrough => output.push_str("on key fallthrough"),
+        DisJump::OnCombineFallthrough => output.push_str("on combine fallthrough"),
+    }
+}
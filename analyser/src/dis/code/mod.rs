@@ -1,13 +1,22 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 
-use crate::{adb::{AdbXref, AdbXrefKind, AdbXrefPathKind, AdbXrefRegionKind, AdbXrefTextKind}, Resources, SCB, SE};
+use serde::Serialize;
 
-use super::{DisCode, DisError};
+use crate::{adb::{AdbXref, AdbXrefKind, AdbXrefPathKind, AdbXrefRegionKind, AdbXrefTextKind}, Resources};
+
+use super::{htmlsan, show_string, DisCode, DisError, DisHandler, DisRendered};
 
 mod cfg;
+pub mod ir;
 pub mod opcodes;
+pub mod opdb;
+pub mod opmap;
+mod types;
+use ir::{BinOp, Expr, UnOp};
 use cfg::Decompiler;
+pub use cfg::{CfgGraph, GraphFormat};
 use opcodes::DisIns;
+use types::SlotType;
 pub use opcodes::DisOp;
 
 #[derive(Clone, Copy, Debug)]
@@ -51,19 +60,19 @@ impl DisOpData {
         self.0
     }
 
-    fn stk_u8(&self) -> DisValue {
+    fn stk_u8(&self) -> Expr {
         assert_eq!(self.1, 1);
-        DisValue::Const(self.0 & 0xFF)
+        Expr::Const(self.0 & 0xFF)
     }
 
-    fn stk_u16(&self) -> DisValue {
+    fn stk_u16(&self) -> Expr {
         assert_eq!(self.1, 2);
-        DisValue::Const(self.0 & 0xFFFF)
+        Expr::Const(self.0 & 0xFFFF)
     }
 
-    fn stk_u32(&self) -> DisValue {
+    fn stk_u32(&self) -> Expr {
         assert_eq!(self.1, 4);
-        DisValue::Const(self.0)
+        Expr::Const(self.0)
     }
 }
 
@@ -71,15 +80,20 @@ fn show_addr(pos: usize) -> String {
     format!("<a href=\"#addr{pos:04x}\">{pos:04x}</a>")
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
-enum DisJump {
+/// Kind of a control flow edge.
+///
+/// Edges out of conditional and event handler opcodes come in pairs: the
+/// fallthrough path carries the condition or the event, the branch target
+/// carries the matching `*Fallthrough` kind.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub enum DisJump {
     Unconditional,
-    Conditional { test: String },
-    Unknown { op: u8 },
+    Conditional { test: Expr },
+    Unknown { op: u8, arg: Option<Expr> },
     OnInit,
     OnInteract(bool),
-    OnKey { key: String },
-    OnCombine { with: String },
+    OnKey { key: Expr },
+    OnCombine { with: Expr },
 
     Straight,
 
@@ -92,26 +106,14 @@ enum DisJump {
 }
 
 impl DisJump {
-    fn show_decomp(&self) -> String {
-        match self {
-            Self::Unconditional => "unconditional".to_string(),
-            Self::Conditional { test } => format!("if ({test})"),
-            Self::Unknown { op } => format!("unknown(0x{op:02x})"),
-            Self::OnInit => "on init".to_string(),
-            Self::OnInteract(false) => "on interact (LMB)".to_string(),
-            Self::OnInteract(true) => "on interact (RMB)".to_string(),
-            Self::OnKey { key } => format!("on key({key})"),
-            Self::OnCombine { with } => format!("on combine({with})"),
-
-            Self::Straight => "straightline".to_string(),
-
-            Self::ConditionalFallthrough => "conditional fallthrough".to_string(),
-            Self::UnknownFallthrough => "unknown fallthrough".to_string(),
-            Self::OnInitFallthrough => "on init fallthrough".to_string(),
-            Self::OnInteractFallthrough => "on interact fallthrough".to_string(),
-            Self::OnKeyFallthrough => "on key fallthrough".to_string(),
-            Self::OnCombineFallthrough => "on combine fallthrough".to_string(),
-        }
+    /// Whether this is the edge of an event handler opcode, to either its
+    /// body or (for unknown opcodes) the code after it.
+    fn is_handler(&self) -> bool {
+        matches!(self, Self::OnInit
+            | Self::OnInteract(_)
+            | Self::OnKey { .. }
+            | Self::OnCombine { .. }
+            | Self::Unknown { .. })
     }
 
     fn is_fallthrough(&self) -> bool {
@@ -124,21 +126,9 @@ impl DisJump {
     }
 }
 
-#[derive(Debug, Clone, Default)]
-enum DisValue {
-    Const(u32),
-    #[allow(dead_code)]
-    String(String), // string *constant*
-    Dynamic(String), // description of contents
-    FifoPos(usize),
-    Unop(&'static str, Box<DisValue>),
-    Binop(&'static str, Box<DisValue>, Box<DisValue>),
-    #[default] Unknown,
-}
-
 #[derive(Default, Clone, Debug)]
 struct DisStack {
-    items: Vec<DisValue>,
+    items: Vec<Expr>,
 }
 
 #[derive(Clone)]
@@ -150,195 +140,121 @@ struct DisSym<'a> {
     jump: Option<DisJump>,
     pos: usize,
     op_stack: DisStack,
-    fifo: VecDeque<Result<String, String>>,
+    fifo: VecDeque<Expr>,
     exit: bool,
     xrefs: Vec<AdbXref>,
 }
 
 impl<'a> DisSym<'a> {
-    fn push(&mut self, item: DisValue) {
+    fn push(&mut self, item: Expr) {
         self.op_stack.items.push(item);
     }
 
-    fn pop(&mut self) -> Result<DisValue, DisError> {
+    fn pop(&mut self) -> Result<Expr, DisError> {
         self.op_stack.items.pop().ok_or(DisError::MalformedCode("cannot pop, stack empty".to_string()))
     }
 
-    fn val_stack(&self, value: &DisValue) -> String {
+    fn val_stack(&self, value: &Expr) -> String {
         match value {
-            DisValue::Const(value) => format!("{value}"),
-            // TODO: precedence, avoid unnecessary parens
-            DisValue::Binop(op, lhs, rhs) => format!("({}) {op} ({})", self.val_stack(lhs), self.val_stack(rhs)),
-            DisValue::Unop(op, val) => format!("{op}({})", self.val_stack(val)),
-            DisValue::FifoPos(idx) => format!("fifo{idx}({})", self.show_res_str(self.fifo[*idx].clone())),
-            _ => format!("{value:?}"),
+            Expr::Fifo(idx) => format!("fifo{idx}({})", self.fifo[*idx]),
+            _ => value.to_string(),
         }
     }
 
-    fn xref_str(&mut self, value: &DisValue, kind: AdbXrefKind) {
-        if let Ok(other_key) = self.eval_str(value) {
-            self.xrefs.push(AdbXref {
-                other_key,
-                loc: None,
-                kind,
-            });
-            return;
-        }
-        match value {
-            DisValue::Binop("+s", lhs, box DisValue::Unop("i2s", box DisValue::Unop("global", _rhs))) => {
-                if let Ok(lhs_key) = self.eval_str(lhs) {
-                    self.xrefs.push(AdbXref {
-                        other_key: lhs_key,
-                        loc: None,
-                        kind: AdbXrefKind::ParentOf(Box::new(kind)),
-                    });
-                }
+    fn xref_str(&mut self, value: &Expr, kind: AdbXrefKind) {
+        match self.eval_str(value) {
+            Expr::Str(other_key) => {
+                self.xrefs.push(AdbXref {
+                    other_key,
+                    loc: None,
+                    kind,
+                });
+            }
+            Expr::Binop(BinOp::Concat, box Expr::Str(lhs_key), box Expr::Unop(UnOp::ToStr, box Expr::Global(_))) => {
+                self.xrefs.push(AdbXref {
+                    other_key: lhs_key,
+                    loc: None,
+                    kind: AdbXrefKind::ParentOf(Box::new(kind)),
+                });
             }
             _ => (),
         }
     }
 
-    fn show_eval_int(&self, value: &DisValue) -> String {
-        self.show_res_int(self.eval_int(value))
-    }
-
-    fn show_res_int(&self, value: Result<u32, String>) -> String {
-        match value {
-            Ok(v) => v.to_string(),
-            Err(s) => s.to_string(),
+    /// Attaches the meaning of `value` for the global `name`, if the global has
+    /// known values.
+    fn global_hint(&self, name: &Expr, value: Expr) -> Expr {
+        if let (Expr::Str(name), Expr::Int(c)) = (name, &value)
+            && let Some(values) = self.res.entries.get(name).and_then(|e| e.global.as_ref()).map(|g| &g.values) {
+            let hint = values.get(c).cloned();
+            return Expr::Hint(Box::new(value), hint);
         }
+        value
     }
 
-    fn show_eval_str(&self, value: &DisValue) -> String {
-        self.show_res_str(self.eval_str(value))
-    }
-
-    fn show_res_str(&self, value: Result<String, String>) -> String {
+    /// Evaluates a value used as a string: constants are string pool indices.
+    fn eval_str(&self, value: &Expr) -> Expr {
         match value {
-            Ok(v) => self.show_string(v),
-            Err(s) => s.to_string(),
-        }
-    }
-
-    fn eval_str(&self, value: &DisValue) -> Result<String, String> {
-        match value {
-            DisValue::Binop(op, lhs, rhs) => match (op, self.eval_str(lhs), self.eval_str(rhs)) {
-                (&"+s", Ok(lhs), Ok(rhs)) => Ok(lhs + &rhs),
-                (op, lhs, rhs) => {
-                    let unknown = lhs.is_ok() && rhs.is_ok();
-                    Err(format!("({}) {op}{} ({})", self.show_res_str(lhs), if unknown { "?" } else { "" }, self.show_res_str(rhs)))
-                }
+            Expr::Const(idx) => match self.strings.get(*idx as usize) {
+                Some(s) => Expr::Str(s.clone()),
+                None => Expr::BadStr(*idx),
             },
-            DisValue::Unop(op, val) => match (op, self.eval_int(val)) {
-                (&"i2s", Ok(val)) => Ok(val.to_string()),
-                (&"i2s", Err(val)) => Err(format!("str({val})")),
-                (op, Ok(val)) => Err(format!("{op}?({val})")),
-                (op, Err(val)) => Err(format!("{op}({val})")),
+            Expr::Binop(BinOp::Concat, lhs, rhs) => match (self.eval_str(lhs), self.eval_str(rhs)) {
+                (Expr::Str(lhs), Expr::Str(rhs)) => Expr::Str(lhs + &rhs),
+                (lhs, rhs) => Expr::binop(BinOp::Concat, lhs, rhs),
             },
-            DisValue::Const(idx) => {
-                if 0 <= (*idx as i32) && (*idx as usize) < self.strings.len() {
-                    return Ok(self.strings[*idx as usize].clone());
-                }
-                //let idxs = idx as i32;
-                //if -11 <= idxs && idxs <= -2 {
-                //    return self.fifo[(idxs - 2) as usize].clone();
-                //}
-                Err(format!("const:{idx}"))
-            }
-            DisValue::Dynamic(desc) => match desc.as_str() {
-                "screen.name" => Err("<span class=\"hl-dyn\">screen</span>.name".to_string()),
-                _ => Err(format!("<span class=\"hl-dyn\">{desc}</span>")),
+            Expr::Unop(UnOp::ToStr, val) => match self.eval_int(val) {
+                Expr::Int(val) => Expr::Str(val.to_string()),
+                val => Expr::unop(UnOp::ToStr, val),
             },
-            DisValue::FifoPos(idx) => self.fifo[*idx].clone(),
-            _ => Err(format!("{value:?}")),
+            Expr::Fifo(idx) => self.fifo[*idx].clone(),
+            _ => self.eval_int(value),
         }
     }
 
-    fn eval_int(&self, value: &DisValue) -> Result<u32, String> {
-        match value {
-            DisValue::Binop("==", box DisValue::Binop("==", lhs, rhs), box DisValue::Const(0)) => return self.eval_int(&DisValue::Binop("!=", lhs.clone(), rhs.clone())),
-            DisValue::Unop("!", box DisValue::Binop("==", lhs, rhs)) => return self.eval_int(&DisValue::Binop("!=", lhs.clone(), rhs.clone())),
-            DisValue::Unop("!", box DisValue::Binop("<", lhs, rhs)) => return self.eval_int(&DisValue::Binop(">=", lhs.clone(), rhs.clone())),
-            DisValue::Unop("!", box DisValue::Binop(">", lhs, rhs)) => return self.eval_int(&DisValue::Binop("<=", lhs.clone(), rhs.clone())),
-            DisValue::Unop("!", box DisValue::Binop("<=", lhs, rhs)) => return self.eval_int(&DisValue::Binop(">", lhs.clone(), rhs.clone())),
-            DisValue::Unop("!", box DisValue::Binop(">=", lhs, rhs)) => return self.eval_int(&DisValue::Binop("<", lhs.clone(), rhs.clone())),
-            _ => (),
+    /// Evaluates an operand of the given type. Operands of unknown type are
+    /// left as they are.
+    fn eval(&self, value: &Expr, ty: SlotType) -> Expr {
+        match ty {
+            SlotType::Int => self.eval_int(value),
+            SlotType::Str => self.eval_str(value),
+            SlotType::Any => value.clone(),
+        }
+    }
+
+    /// Evaluates a value used as an integer, folding constant operations.
+    fn eval_int(&self, value: &Expr) -> Expr {
+        if let Some(folded) = value.fold_negation() {
+            return self.eval_int(&folded);
         }
         match value {
-            DisValue::Const(value) => Ok(*value),
-            DisValue::Binop("==s", lhs, rhs) => match (self.eval_str(lhs), self.eval_str(rhs)) {
-                (Ok(lhs), Ok(rhs)) => Ok(if lhs == rhs { 1 } else { 0 }),
-                (lhs, rhs) => Err(format!("({}) ==s ({})", self.show_res_str(lhs),  self.show_res_str(rhs))),
+            Expr::Const(value) => Expr::Int(*value),
+            Expr::Binop(BinOp::StrEq, lhs, rhs) => match (self.eval_str(lhs), self.eval_str(rhs)) {
+                (Expr::Str(lhs), Expr::Str(rhs)) => Expr::Int((lhs == rhs) as u32),
+                (lhs, rhs) => Expr::binop(BinOp::StrEq, lhs, rhs),
             },
-            DisValue::Binop(op, lhs, rhs) => match (op, self.eval_int(lhs), self.eval_int(rhs)) {
-                (&"==", Ok(lhs), Ok(rhs)) => Ok(if lhs == rhs { 1 } else { 0 }),
-                (&"!=", Ok(lhs), Ok(rhs)) => Ok(if lhs != rhs { 1 } else { 0 }),
-                (&"<",  Ok(lhs), Ok(rhs)) => Ok(if lhs < rhs { 1 } else { 0 }),
-                (&">",  Ok(lhs), Ok(rhs)) => Ok(if lhs > rhs { 1 } else { 0 }),
-                (&"<=", Ok(lhs), Ok(rhs)) => Ok(if lhs <= rhs { 1 } else { 0 }),
-                (&">=", Ok(lhs), Ok(rhs)) => Ok(if lhs >= rhs { 1 } else { 0 }),
-                (&"+",  Ok(lhs), Ok(rhs)) => Ok(lhs + rhs),
-                (&"-",  Ok(lhs), Ok(rhs)) => Ok(lhs - rhs),
-                (&"*",  Ok(lhs), Ok(rhs)) => Ok(lhs * rhs),
-                (&"/",  Ok(lhs), Ok(rhs)) => Ok(lhs / rhs),
-                (&"%",  Ok(lhs), Ok(rhs)) => Ok(lhs % rhs),
-                (&"&",  Ok(lhs), Ok(rhs)) => Ok(lhs & rhs),
-                (&"|",  Ok(lhs), Ok(rhs)) => Ok(lhs | rhs),
-                (&"^",  Ok(lhs), Ok(rhs)) => Ok(lhs ^ rhs),
-                (&"<<", Ok(lhs), Ok(rhs)) => Ok(lhs << rhs),
-                (&">>", Ok(lhs), Ok(rhs)) => Ok(lhs >> rhs),
-                (&"&&", Ok(lhs), Ok(rhs)) => Ok(if lhs != 0 && rhs != 0 { 1 } else { 0 }),
-                (&"||", Ok(lhs), Ok(rhs)) => Ok(if lhs != 0 || rhs != 0 { 1 } else { 0 }),
-                (op, Ok(lhs), Ok(rhs)) => Err(format!("({lhs}) {op}? ({rhs})")),
-                (op, reslhs, resrhs) => {
-                    match (op, &reslhs, &resrhs) {
-                        (&"==", Ok(c), _) if matches!(**rhs, DisValue::Unop("global", _)) => {
-                            let DisValue::Unop(_, name) = *rhs.clone() else { unreachable!(); };
-                            if let Ok(name) = self.eval_str(&name)
-                                && let Some(values) = self.res.entries.get(&name).and_then(|e| e.global.as_ref()).map(|g| &g.values) {
-                                if let Some(hint) = values.get(&c) {
-                                    return Err(format!("({} {SCB}{hint}{SE}) {op} ({})", self.show_res_int(reslhs), self.show_res_int(resrhs)))
-                                } else {
-                                    return Err(format!("({} {SCB}?{SE}) {op} ({})", self.show_res_int(reslhs), self.show_res_int(resrhs)))
-                                }
-                            }
-                        },
-                        _ => (),
-                    }
-                    Err(format!("({}) {op} ({})", self.show_res_int(reslhs), self.show_res_int(resrhs)))
+            Expr::Binop(BinOp::Concat, ..) => self.eval_str(value),
+            Expr::Binop(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.eval_int(lhs), self.eval_int(rhs));
+                if let (Expr::Int(lhs), Expr::Int(rhs)) = (&lhs, &rhs)
+                    && let Some(value) = op.apply(*lhs, *rhs) {
+                    return Expr::Int(value);
                 }
+                if *op == BinOp::Eq && let Expr::Global(name) = &rhs {
+                    return Expr::binop(*op, self.global_hint(name, lhs), rhs);
+                }
+                Expr::binop(*op, lhs, rhs)
+            }
+            Expr::Unop(op, val) => match (op, self.eval_int(val)) {
+                (UnOp::Neg, Expr::Int(val)) => Expr::Int(val.wrapping_neg()),
+                (UnOp::BitNot, Expr::Int(val)) => Expr::Int(!val),
+                (UnOp::Not, Expr::Int(val)) => Expr::Int((val == 0) as u32),
+                (UnOp::ToStr, _) => self.eval_str(value),
+                (op, val) => Expr::unop(*op, val),
             },
-            DisValue::Unop(op, val) => match (op, self.eval_int(val), self.eval_str(val)) {
-                (&"-", Ok(val), _) => Ok(-(val as i32) as u32),
-                (&"~", Ok(val), _) => Ok(0xFFFFFFFF ^ val),
-                (&"!", Ok(val), _) => Ok(if val == 0 { 1 } else { 0 }),
-                (&"cursors", _, val) => Err(format!("<span class=\"hl-dyn\">cursors</span>[{}]", self.show_res_str(val))),
-                (&"global", _, val) => Err(format!("<span class=\"hl-dyn\">global</span>[{}]", self.show_res_str(val))),
-                (&"inv.has", _, val) => Err(format!("<span class=\"hl-dyn\">inv</span>.has({})", self.show_res_str(val))),
-                (&"object.x", _, val) => Err(format!("obj[{}].x", self.show_res_str(val))),
-                (&"object.y", _, val) => Err(format!("obj[{}].y", self.show_res_str(val))),
-                (&"object.w", _, val) => Err(format!("obj[{}].w", self.show_res_str(val))),
-                (&"object.h", _, val) => Err(format!("obj[{}].h", self.show_res_str(val))),
-                (&"random", val, _) => Err(format!("<span class=\"hl-dyn\">random</span>({})", self.show_res_int(val))),
-                (&"region.x", _, val) => Err(format!("region[{}].x", self.show_res_str(val))),
-                (&"region.y", _, val) => Err(format!("region[{}].y", self.show_res_str(val))),
-                (&"screenpatch", _, val) => Err(format!("<span class=\"hl-dyn\">screen</span>.patch({})", self.show_res_str(val))),
-                (&"vars", _, val) => Err(format!("<span class=\"hl-dyn\">vars</span>[{}]", self.show_res_str(val))),
-                (op, Ok(val), _) => Err(format!("{op}?({val})")),
-                (op, Err(val), _) => Err(format!("{op}({val})")),
-            },
-            DisValue::Dynamic(desc) => match desc.as_str() {
-                "mouse.x" => Err("<span class=\"hl-dyn\">mouse</span>.x".to_string()),
-                "mouse.y" => Err("<span class=\"hl-dyn\">mouse</span>.y".to_string()),
-                _ => Err(format!("<span class=\"hl-dyn\">{desc}</span>")),
-            },
-            _ => Err(format!("{value:?}")),
+            _ => value.clone(),
         }
     }
-
-    fn show_string(&self, s: String) -> String {
-        super::show_string(&s, self.res)
-    }
 }
 
 pub fn analyse(
@@ -347,7 +263,7 @@ pub fn analyse(
     strings: &[String],
     res: Resources,
     output: &mut DisCode,
-) -> Result<String, DisError> {
+) -> Result<DisRendered, DisError> {
     let mut queue = VecDeque::new();
     queue.push_back(DisSym {
         code_start,
@@ -366,7 +282,9 @@ pub fn analyse(
         Data,
     }
     let mut marked = HashMap::new();
-    let mut decompiler = Decompiler::new(code_start, code);
+    let mut decompiler = Decompiler::new(code_start, code, &res.profile.map);
+    let mut ops = BTreeMap::new();
+    let mut succ = HashMap::new();
     while let Some(head) = queue.pop_front() {
         if code.len() <= head.pos {
             return Err(DisError::MalformedCode(format!("pos {:04x} exceeds code length {}", code_start + head.pos, code.len())));
@@ -377,7 +295,7 @@ pub fn analyse(
             Some(ByteMark::Data) => return Err(DisError::MalformedCode(format!("pos {:04x} marked as op (previously marked as data)", code_start + head.pos))),
             None => { marked.insert(head.pos, ByteMark::Op { stack_len: head.op_stack.items.len() }); }
         }
-        let (pos, op) = match DisIns::analyse_one(code, head.pos) {
+        let (pos, op) = match DisIns::analyse_one(code, head.pos, &res.profile.map) {
             Ok(v) => v,
             Err(err) => {
                 output.error = true;
@@ -413,7 +331,7 @@ pub fn analyse(
                 continue;
             }
         };
-        if let Some(decomp) = decomp.as_ref() {
+        if !decomp.is_empty() {
             decompiler.add_decomp(head_pos, decomp.clone());
         }
         for s in &mut next_sym {
@@ -423,19 +341,33 @@ pub fn analyse(
                     ..xref
                 }));
             if let Some(jump) = s.jump.as_ref() {
+                if jump.is_handler() && let Some(target) = op.jump_target(head_pos) {
+                    output.handlers.push(DisHandler {
+                        addr: code_start + head_pos,
+                        trigger: DisRendered {
+                            html: jump.html(res),
+                            text: jump.text(res),
+                        },
+                        condition: None,
+                        body: code_start + pos..code_start + target as usize,
+                        script: None,
+                    });
+                }
                 decompiler.add_jump(head_pos, s.pos, jump.clone());
                 output.line(
                     s.pos,
                     s.pos,
                     None,
                     None,
-                    Some(format!("<span class=\"jump\"></span>{} from {}", jump.show_decomp(), show_addr(code_start + head_pos))),
+                    Some(format!("<span class=\"jump\"></span>{} from {}", jump.html(res), show_addr(code_start + head_pos))),
                 );
             }
             s.jump = None;
         }
         //println!("at {:04x}: jumps: {:?}", code_start + head_pos, pos_jump.get(&head_pos));
-        let stack = next_sym[0].op_stack.items.iter().map(|e| next_sym[0].val_stack(e)).collect::<Vec<_>>();
+        let stack = next_sym[0].op_stack.items.iter().map(|e| htmlsan(&next_sym[0].val_stack(e))).collect::<Vec<_>>();
+        let decomp = (!decomp.is_empty())
+            .then(|| decomp.iter().map(|stmt| stmt.html(res)).collect::<Vec<_>>().join("\n"));
         output.line(
             head_pos,
             pos,
@@ -444,13 +376,51 @@ pub fn analyse(
             // Some(format!("S: {:?}; pcs: {:?}", next_sym[0].op_stack, next_sym.iter().map(|s| format!("{:04x}", code_start + s.pos)).collect::<Vec<_>>())),
             Some(format!("S: [{}]", stack.join(", "))),
         );
-        queue.extend(next_sym.into_iter().filter(|s| !s.exit));
+        let next_sym = next_sym.into_iter().filter(|s| !s.exit).collect::<Vec<_>>();
+        succ.insert(head_pos, next_sym.iter().map(|s| s.pos).collect::<Vec<_>>());
+        ops.insert(head_pos, op);
+        queue.extend(next_sym);
     }
+    output.handlers.sort_by_key(|handler| handler.addr);
     if !output.error {
+        let types = types::check(&ops, &succ, code_start);
+        for ((pos, ..), msg) in types.diagnostics {
+            output.line(pos, pos, None, None, Some(format!("<span class=\"hl-err\">type</span> {}", htmlsan(&msg))));
+            output.diagnostics.push(format!("{:04x}: {msg}", code_start + pos));
+        }
+        for (pos, ty) in types.consts {
+            if ty != SlotType::Str {
+                continue;
+            }
+            let idx = match ops[&pos].op {
+                DisOp::Push35 => 35,
+                _ => ops[&pos].imm_value(),
+            };
+            let comment = match strings.get(idx as usize) {
+                Some(s) => format!("used as string {}", show_string(s, res)),
+                None => {
+                    output.diagnostics.push(format!("{:04x}: string index {idx} out of range", code_start + pos));
+                    format!("<span class=\"hl-err\">type</span> used as string, but index {idx} is out of range")
+                }
+            };
+            output.line(pos, pos, None, None, Some(comment));
+        }
+        output.diagnostics.sort();
+        if res.do_cfg {
+            output.cfg = decompiler.graph().ok();
+        }
         if res.do_analyse {
-            decompiler.analyse()
+            let (pretty, unstructured, scripts) = decompiler.analyse(res)?;
+            output.unstructured = unstructured;
+            for script in scripts {
+                if let Some(handler) = output.handlers.iter_mut().find(|h| h.addr == code_start + script.pos) {
+                    handler.condition = script.condition;
+                    handler.script = Some(script.script);
+                }
+            }
+            Ok(pretty)
         } else {
-            Ok("".to_string())
+            Ok(DisRendered::default())
         }
     } else {
         Err(DisError::MalformedCode("".to_string()))
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use super::DisOp;
+
+/// Findings about one opcode, recorded outside of the `opcodes!` table.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OpcodeInfo {
+    /// Name shown instead of the `unkXX` placeholder in decompiled code.
+    pub name: Option<String>,
+    /// Shown next to the arguments, instead of the built-in guess (if any).
+    pub description: Option<String>,
+    /// Labels of the arguments, in the order they are shown in decompiled
+    /// code, which is the order they are pushed.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Opcode names, descriptions and argument labels loaded at runtime. Only
+/// opcodes which are decompiled into unknown calls (`Unk*`) can be labelled,
+/// everything else already has a decompiled form.
+#[derive(Default)]
+pub struct OpcodeDb {
+    ops: HashMap<usize, OpcodeInfo>,
+}
+
+impl OpcodeDb {
+    /// Parses a JSON object, keyed by opcode mnemonic, e.g.
+    /// `{"UnkC8": {"name": "sample.volume", "args": ["channel", "volume"]}}`.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let parsed: HashMap<String, OpcodeInfo> = serde_json::from_str(source).map_err(|err| err.to_string())?;
+        let mut ops = HashMap::new();
+        for (mnemonic, info) in parsed {
+            let Some(op) = (0..256).find(|idx| DisOp::NAME[*idx].eq_ignore_ascii_case(&mnemonic)) else {
+                return Err(format!("no such opcode: {mnemonic}"));
+            };
+            if !DisOp::NAME[op].starts_with("Unk") {
+                return Err(format!("only unknown opcodes can be labelled: {mnemonic}"));
+            }
+            if info.args.len() > DisOp::STACK_IN[op] {
+                return Err(format!("{mnemonic} takes {} argument(s), but {} labels are given", DisOp::STACK_IN[op], info.args.len()));
+            }
+            ops.insert(op, info);
+        }
+        Ok(Self { ops })
+    }
+
+    pub fn get(&self, op: usize) -> Option<&OpcodeInfo> {
+        self.ops.get(&op)
+    }
+
+    /// Finds the opcode of an unknown statement by its placeholder name,
+    /// e.g. `unkC8`.
+    pub fn get_by_name(&self, name: &str) -> Option<&OpcodeInfo> {
+        self.ops.iter()
+            .find(|(op, _)| DisOp::NAME[**op].eq_ignore_ascii_case(name))
+            .map(|(_, info)| info)
+    }
+}
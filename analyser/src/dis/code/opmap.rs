@@ -0,0 +1,285 @@
+use crate::{asm, encoding::TextEncoding};
+
+use super::DisOp;
+
+/// Opcode maps shipped with the analyser, by game version. The first one is
+/// the default.
+const BUILTIN: &[(&str, &str)] = &[
+    ("1.0en", include_str!("maps/1.0en.map")),
+    ("1.0pl", include_str!("maps/1.0pl.map")),
+    ("1.03bu", include_str!("maps/1.03bu.map")),
+];
+
+/// Permutation of opcode bytes used by one game version: byte `b` in a code
+/// object encodes the opcode `DisOp::VARIANTS[ops[b]]`.
+///
+/// In a map file, every line maps one byte, or a range of bytes, to the
+/// opcode of the first byte, e.g. `18-cd 42` maps `18` to `42`, `19` to `43`,
+/// and so on. Values are in hex, `#` starts a comment. Bytes which are not
+/// listed encode the opcode of the same value. A line `encoding <label>` gives
+/// the text encoding of the strings of the game version. Every opcode must be
+/// encoded by exactly one byte.
+#[derive(Clone, PartialEq, Eq)]
+pub struct OpcodeMap {
+    pub ops: [u8; 256],
+    pub encoding: Option<String>,
+}
+
+impl OpcodeMap {
+    pub fn identity() -> Self {
+        Self {
+            ops: std::array::from_fn(|i| i as u8),
+            encoding: None,
+        }
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut map = Self::identity();
+        for (idx, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let hex = |s: &str| u8::from_str_radix(s, 16).map_err(|_| format!("line {}: invalid byte {s:?}", idx + 1));
+            let Some((bytes, op)) = line.split_once(char::is_whitespace) else {
+                return Err(format!("line {}: expected a byte and an opcode", idx + 1));
+            };
+            let op = op.trim();
+            if bytes == "encoding" {
+                if TextEncoding::for_label(op).is_none() {
+                    return Err(format!("line {}: no such encoding {op:?}", idx + 1));
+                }
+                map.encoding = Some(op.to_string());
+                continue;
+            }
+            let (first, last) = match bytes.split_once('-') {
+                Some((first, last)) => (hex(first)?, hex(last)?),
+                None => (hex(bytes)?, hex(bytes)?),
+            };
+            let op = hex(op)?;
+            if last < first || op as usize + (last - first) as usize > 0xFF {
+                return Err(format!("line {}: invalid range", idx + 1));
+            }
+            for byte in first..=last {
+                map.ops[byte as usize] = op + (byte - first);
+            }
+        }
+        let mut count = [0; 256];
+        for op in map.ops {
+            count[op as usize] += 1;
+        }
+        let list = |want: fn(usize) -> bool| (0..256)
+            .filter(|op| want(count[*op]))
+            .map(|op| format!("{op:02x}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let duplicated = list(|n| n > 1);
+        if !duplicated.is_empty() {
+            return Err(format!("opcodes encoded by more than one byte: {duplicated}; missing opcodes: {}", list(|n| n == 0)));
+        }
+        Ok(map)
+    }
+
+    /// Finds the byte which encodes the given opcode.
+    pub fn byte_of(&self, op: DisOp) -> u8 {
+        self.ops.iter()
+            .position(|b| *b == op as u8)
+            .expect("opcode not in mapping") as u8
+    }
+
+    /// Names of the built-in opcode maps.
+    pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+        BUILTIN.iter().map(|(name, _)| *name)
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        BUILTIN.iter()
+            .find(|(other, _)| *other == name)
+            .map(|(_, source)| Self::parse(source).expect("invalid built-in opcode map"))
+    }
+
+    /// Finds a built-in map by name, or else reads a map file from the given
+    /// path.
+    pub fn load(id: &str) -> Result<Self, String> {
+        if let Some(map) = Self::builtin(id) {
+            return Ok(map);
+        }
+        let source = std::fs::read_to_string(id).map_err(|err| format!("no such opcode map: {id} ({err})"))?;
+        Self::parse(&source)
+    }
+
+    /// Writes the map in the format accepted by `parse`, with the given
+    /// header comment.
+    pub fn to_source(&self, header: &str) -> String {
+        let mut ret = String::new();
+        for line in header.lines() {
+            ret.push_str(&format!("# {line}\n"));
+        }
+        if let Some(encoding) = &self.encoding {
+            ret.push_str(&format!("encoding {encoding}\n"));
+        }
+        let mut byte = 0;
+        while byte < 256 {
+            if self.ops[byte] as usize == byte {
+                byte += 1;
+                continue;
+            }
+            let first = byte;
+            while byte < 255 && self.ops[byte + 1] as usize != byte + 1 && self.ops[byte + 1] as usize == self.ops[byte] as usize + 1 {
+                byte += 1;
+            }
+            if byte == first {
+                ret.push_str(&format!("{first:02x} {:02x}\n", self.ops[first]));
+            } else {
+                ret.push_str(&format!("{first:02x}-{byte:02x} {:02x}\n", self.ops[first]));
+            }
+            byte += 1;
+        }
+        ret
+    }
+}
+
+/// Map derived by `MapDeriver`.
+pub struct DerivedMap {
+    pub map: OpcodeMap,
+    /// Number of bytes seen in the object pairs.
+    pub observed: usize,
+    /// Number of bytes filled in from the bytes around them.
+    pub inferred: usize,
+    /// Bytes which could not be derived. They are given the opcodes left over,
+    /// their own value where it is free.
+    pub unknown: Vec<u8>,
+}
+
+/// Derives the opcode map of a new game version from pairs of code objects
+/// which are known to be equivalent: the same object in a version with a
+/// known map, and in the new version.
+pub struct MapDeriver<'a> {
+    known: &'a OpcodeMap,
+    seen: [Option<u8>; 256],
+}
+
+impl<'a> MapDeriver<'a> {
+    pub fn new(known: &'a OpcodeMap) -> Self {
+        Self {
+            known,
+            seen: [None; 256],
+        }
+    }
+
+    /// Adds the code sections of an equivalent pair. The pair is rejected if
+    /// the known code cannot be decoded, if anything other than the opcode
+    /// bytes differs, or if it disagrees with the pairs added before.
+    /// Returns the number of instructions matched.
+    pub fn add_pair(&mut self, known: &[u8], new: &[u8]) -> Result<usize, String> {
+        if known.len() != new.len() {
+            return Err(format!("code sizes differ: {} and {} bytes", known.len(), new.len()));
+        }
+        let mut seen = self.seen;
+        let mut count = 0;
+        for (pos, ins) in asm::sweep(known, self.known) {
+            let Some(ins) = ins else {
+                return Err(format!("cannot decode known code at {:04x}", 0x18 + pos));
+            };
+            let end = pos + 1 + DisOp::IMM_SIZE[ins.op_byte as usize];
+            if known[pos + 1..end] != new[pos + 1..end] {
+                return Err(format!("immediates differ at {:04x}", 0x18 + pos));
+            }
+            let byte = new[pos] as usize;
+            match seen[byte] {
+                Some(op) if op != ins.op_byte => return Err(format!(
+                    "byte {byte:02x} at {:04x} is {}, but was {} before",
+                    0x18 + pos,
+                    DisOp::NAME[ins.op_byte as usize],
+                    DisOp::NAME[op as usize],
+                )),
+                _ => seen[byte] = Some(ins.op_byte),
+            }
+            count += 1;
+        }
+        self.seen = seen;
+        Ok(count)
+    }
+
+    /// Builds the map. Bytes which were not seen are filled in when the
+    /// nearest seen bytes on both sides are shifted by the same amount, as
+    /// maps tend to consist of a few shifted ranges.
+    pub fn finish(self) -> DerivedMap {
+        let mut map = OpcodeMap::identity();
+        let observed = self.seen.iter().flatten().count();
+        for (byte, op) in self.seen.iter().enumerate() {
+            if let Some(op) = op {
+                map.ops[byte] = *op;
+            }
+        }
+        let mut taken = [false; 256];
+        for op in self.seen.iter().flatten() {
+            taken[*op as usize] = true;
+        }
+        let shift = |byte: usize| self.seen[byte].map(|op| op as isize - byte as isize);
+        let mut inferred = 0;
+        let mut unknown = Vec::new();
+        for byte in 0..256 {
+            if self.seen[byte].is_some() {
+                continue;
+            }
+            let below = (0..byte).rev().find_map(shift);
+            let above = (byte + 1..256).find_map(shift);
+            let op = match (below, above) {
+                (Some(a), Some(b)) if a == b => Some(byte as isize + a),
+                _ => None,
+            };
+            match op {
+                Some(op) if (0..256).contains(&op) && !taken[op as usize] => {
+                    map.ops[byte] = op as u8;
+                    taken[op as usize] = true;
+                    inferred += 1;
+                }
+                _ => unknown.push(byte as u8),
+            }
+        }
+        let mut clashing = Vec::new();
+        for byte in &unknown {
+            if taken[*byte as usize] {
+                clashing.push(*byte as usize);
+            } else {
+                taken[*byte as usize] = true;
+            }
+        }
+        let free = (0..256).filter(|op| !taken[*op]);
+        for (byte, op) in clashing.into_iter().zip(free) {
+            map.ops[byte] = op as u8;
+        }
+        DerivedMap {
+            map,
+            observed,
+            inferred,
+            unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rejects_duplicated_opcodes() {
+        let err = OpcodeMap::parse("10 11\n").err().unwrap();
+        assert_eq!(err, "opcodes encoded by more than one byte: 11; missing opcodes: 10");
+        assert!(OpcodeMap::parse("10 11\n11 10\n").is_ok());
+    }
+
+    #[test]
+    fn unknown_bytes_get_free_opcodes() {
+        let known = OpcodeMap::identity();
+        let mut deriver = MapDeriver::new(&known);
+        deriver.seen[0x10] = Some(0x11);
+        let derived = deriver.finish();
+        assert!(derived.unknown.contains(&0x11));
+        let mut ops = derived.map.ops;
+        ops.sort();
+        assert_eq!(ops, OpcodeMap::identity().ops);
+        assert!(OpcodeMap::parse(&derived.map.to_source("")).unwrap() == derived.map);
+    }
+}
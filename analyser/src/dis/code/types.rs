@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+use super::{opcodes::DisIns, DisOp};
+
+/// Type of a value on the operand stack.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SlotType {
+    Int,
+    /// Index into the string pool, or a string built at runtime.
+    Str,
+    /// Unknown, or conflicting.
+    Any,
+}
+
+impl SlotType {
+    fn describe(&self) -> &'static str {
+        match self {
+            Self::Int => "an int",
+            Self::Str => "a string",
+            Self::Any => "a value",
+        }
+    }
+}
+
+/// Type of a value pushed by an opcode.
+#[derive(Clone, Copy)]
+enum Pushed {
+    /// Immediate constant, typed by its uses.
+    Const,
+    Type(SlotType),
+    /// Copy of the given operand.
+    Operand(usize),
+}
+
+impl DisOp {
+    /// Type of the values the opcode pushes.
+    fn pushed_type(&self) -> Pushed {
+        match self {
+            Self::PushImm32 | Self::PushImm16a | Self::PushImm8a | Self::PushImm16b | Self::PushImm8b
+            | Self::Push35 => Pushed::Const,
+            Self::Dup => Pushed::Operand(0),
+            Self::GlbSet | Self::GlbAdd | Self::GlbSub | Self::GlbMul | Self::GlbDiv | Self::GlbMod
+            | Self::GlbShl | Self::GlbShr | Self::GlbBitAnd | Self::GlbBirOr | Self::GlbBitXor => Pushed::Operand(1),
+            Self::CloneName | Self::CloneSelf | Self::ToFifo => Pushed::Type(SlotType::Str),
+            // Pushed by statements with unknown meaning.
+            Self::Unk13 | Self::Unk14 | Self::Unk8D | Self::UnkB4 | Self::UnkBA | Self::UnkBB
+            | Self::UnkCD | Self::UnkEA | Self::UnkEC | Self::CloneSetVar => Pushed::Type(SlotType::Any),
+            _ => Pushed::Type(SlotType::Int),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct Slot {
+    /// `None` for constants, until a use decides their type.
+    ty: Option<SlotType>,
+    /// Positions of the instructions which may have pushed this value.
+    from: BTreeSet<usize>,
+}
+
+/// Result of [`check`].
+#[derive(Default)]
+pub(super) struct TypeCheck {
+    /// Diagnostics by position relative to the code start, kind and slot.
+    /// Only the first one is kept when revisiting a position.
+    pub(super) diagnostics: BTreeMap<(usize, &'static str, usize), String>,
+    /// Inferred type of every constant push, by position.
+    pub(super) consts: BTreeMap<usize, SlotType>,
+}
+
+impl TypeCheck {
+    fn report(&mut self, pos: usize, kind: &'static str, idx: usize, msg: String) {
+        self.diagnostics.entry((pos, kind, idx)).or_insert(msg);
+    }
+
+    /// Records that the constants among `from` are used as `ty`.
+    fn resolve(&mut self, ops: &BTreeMap<usize, DisIns>, from: &BTreeSet<usize>, ty: SlotType, code_start: usize) {
+        if ty == SlotType::Any {
+            return;
+        }
+        for pos in from {
+            if !ops.get(pos).is_some_and(|ins| matches!(ins.op.pushed_type(), Pushed::Const)) {
+                continue;
+            }
+            match self.consts.get(pos).copied() {
+                None => {
+                    self.consts.insert(*pos, ty);
+                }
+                Some(SlotType::Any) => (),
+                Some(prev) if prev != ty => {
+                    self.consts.insert(*pos, SlotType::Any);
+                    self.report(*pos, "const", 0, format!("constant at {:04x} is used both as an int and as a string", code_start + pos));
+                }
+                Some(_) => (),
+            }
+        }
+    }
+
+    fn join(&mut self, ops: &BTreeMap<usize, DisIns>, pos: usize, idx: usize, a: &Slot, b: &Slot, code_start: usize) -> Slot {
+        let from = a.from.union(&b.from).copied().collect::<BTreeSet<_>>();
+        let ty = match (a.ty, b.ty) {
+            (None, ty) | (ty, None) => ty,
+            (Some(a), Some(b)) if a == b => Some(a),
+            (Some(SlotType::Any), _) | (_, Some(SlotType::Any)) => Some(SlotType::Any),
+            (Some(_), Some(_)) => {
+                self.report(pos, "join", idx, format!("stack slot {idx} is an int on one path and a string on another (pushed at {})", show_from(&from, code_start)));
+                Some(SlotType::Any)
+            }
+        };
+        if let Some(ty) = ty {
+            self.resolve(ops, &from, ty, code_start);
+        }
+        Slot { ty, from }
+    }
+}
+
+fn show_from(from: &BTreeSet<usize>, code_start: usize) -> String {
+    from.iter().map(|pos| format!("{:04x}", code_start + pos)).collect::<Vec<_>>().join(", ")
+}
+
+/// Infers whether each stack slot holds an int or a string, by abstract
+/// interpretation over the instructions `ops` and their successors `succ`,
+/// as found by [`super::analyse`].
+///
+/// Reports operands of the wrong type, slots whose type differs between
+/// paths, constants used as both, and values left on the stack when the code
+/// exits. Wrong operand lists or `STACK_OUT` entries for unknown opcodes
+/// usually show up as one of these.
+pub(super) fn check(ops: &BTreeMap<usize, DisIns>, succ: &HashMap<usize, Vec<usize>>, code_start: usize) -> TypeCheck {
+    let mut result = TypeCheck::default();
+    let mut states: HashMap<usize, Vec<Slot>> = HashMap::from([(0, Vec::new())]);
+    let mut queue = VecDeque::from([0]);
+    while let Some(pos) = queue.pop_front() {
+        let Some(ins) = ops.get(&pos) else {
+            continue;
+        };
+        let mut stack = states[&pos].clone();
+        let stack_in = DisOp::STACK_IN[ins.op_byte as usize];
+        if stack.len() < stack_in {
+            // Already reported by `analyse`.
+            continue;
+        }
+        let operands = stack.split_off(stack.len() - stack_in)
+            .into_iter()
+            .rev()
+            .collect::<Vec<_>>();
+        let name = DisOp::NAME[ins.op_byte as usize];
+        for (idx, slot) in operands.iter().enumerate() {
+            let expected = DisOp::OPERAND_TYPES[ins.op_byte as usize].get(idx).copied().unwrap_or(SlotType::Any);
+            match slot.ty {
+                _ if expected == SlotType::Any => (),
+                None => result.resolve(ops, &slot.from, expected, code_start),
+                Some(ty) if ty != expected && ty != SlotType::Any => {
+                    result.report(pos, "operand", idx, format!(
+                        "{name} expects {} as operand {}, got {} pushed at {}",
+                        expected.describe(),
+                        idx + 1,
+                        ty.describe(),
+                        show_from(&slot.from, code_start),
+                    ));
+                }
+                Some(_) => (),
+            }
+        }
+        for _ in 0..DisOp::STACK_OUT[ins.op_byte as usize] {
+            stack.push(match ins.op.pushed_type() {
+                Pushed::Const => Slot { ty: None, from: [pos].into() },
+                Pushed::Type(ty) => Slot { ty: Some(ty), from: [pos].into() },
+                Pushed::Operand(idx) => operands[idx].clone(),
+            });
+        }
+
+        let next = succ.get(&pos).map(Vec::as_slice).unwrap_or_default();
+        if next.is_empty() && !stack.is_empty() {
+            result.report(pos, "exit", 0, format!(
+                "{} value(s) left on the stack at exit, pushed at {}",
+                stack.len(),
+                show_from(&stack.iter().flat_map(|slot| slot.from.iter().copied()).collect(), code_start),
+            ));
+        }
+        for to in next {
+            let Some(prev) = states.get(to) else {
+                states.insert(*to, stack.clone());
+                queue.push_back(*to);
+                continue;
+            };
+            if prev.len() != stack.len() {
+                // Already reported by `analyse`.
+                continue;
+            }
+            let joined = prev.clone()
+                .iter()
+                .zip(&stack)
+                .enumerate()
+                .map(|(idx, (a, b))| result.join(ops, *to, idx, a, b, code_start))
+                .collect::<Vec<_>>();
+            if states[to] != joined {
+                states.insert(*to, joined);
+                queue.push_back(*to);
+            }
+        }
+    }
+    result
+}
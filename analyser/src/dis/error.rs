@@ -6,5 +6,5 @@ pub enum DisError {
     LengthMismatch,
     MalformedString,
     MalformedCode(String),
-    CfgAnalysisFailed,
+    CfgAnalysisFailed(String),
 }
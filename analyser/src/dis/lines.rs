@@ -1,5 +1,8 @@
-use crate::{adb::AdbXref, patches::{Patch, PatchChange}};
+use serde::Serialize;
 
+use crate::{adb::AdbXref, dis::code::CfgGraph, patches::{Patch, PatchChange}};
+
+#[derive(Serialize)]
 pub struct DisLine {
     pub span: std::ops::Range<usize>,
     pub hex: String,
@@ -8,6 +11,37 @@ pub struct DisLine {
     pub comments: Option<String>,
 }
 
+/// Decompiled code, rendered from the IR both as HTML, for the pages, and as
+/// plain text, for the text and JSON output. Serialised as the plain text.
+#[derive(Clone, Default)]
+pub struct DisRendered {
+    pub html: String,
+    pub text: String,
+}
+
+impl Serialize for DisRendered {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.text)
+    }
+}
+
+/// Event handler of a code object: code run when the object is set up, or
+/// when the player interacts with it. Offsets are relative to the object, like
+/// the spans of lines.
+#[derive(Clone, Serialize)]
+pub struct DisHandler {
+    /// Offset of the handler opcode.
+    pub addr: usize,
+    /// What runs the handler, e.g. `on combine("item")`.
+    pub trigger: DisRendered,
+    /// Tests of the enclosing conditionals, which must hold when the handler
+    /// is set up.
+    pub condition: Option<DisRendered>,
+    pub body: std::ops::Range<usize>,
+    /// Decompiled body, if the code was structured.
+    pub script: Option<DisRendered>,
+}
+
 pub struct DisCode<'a> {
     pub error: bool,
     pub lines: Vec<DisLine>,
@@ -15,6 +49,13 @@ pub struct DisCode<'a> {
     offset: usize,
     first_pass: bool,
     pub xrefs: Vec<AdbXref>,
+    pub cfg: Option<CfgGraph>,
+    /// Regions the decompiler could not structure, and printed with `goto`s.
+    pub unstructured: Vec<String>,
+    /// Stack type conflicts found by the type check.
+    pub diagnostics: Vec<String>,
+    /// Event handlers, in the order of their opcodes.
+    pub handlers: Vec<DisHandler>,
 }
 
 impl<'a> DisCode<'a> {
@@ -26,6 +67,10 @@ impl<'a> DisCode<'a> {
             offset: 0,
             first_pass,
             xrefs: Vec::new(),
+            cfg: None,
+            unstructured: Vec::new(),
+            diagnostics: Vec::new(),
+            handlers: Vec::new(),
         }
     }
 
@@ -148,6 +193,10 @@ impl<'a> DisCode<'a> {
             offset: self.offset,
             first_pass: self.first_pass,
             xrefs: self.xrefs,
+            cfg: self.cfg,
+            unstructured: self.unstructured,
+            diagnostics: self.diagnostics,
+            handlers: self.handlers,
         }
     }
 }
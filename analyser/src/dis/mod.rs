@@ -4,6 +4,8 @@ mod lines;
 
 use std::collections::HashMap;
 
+use serde::Serialize;
+
 pub use error::*;
 pub use lines::*;
 
@@ -98,7 +100,25 @@ pub fn show_string(s: &str, res: Resources) -> String {
     }
 }
 
-pub fn analyse_region<'a>(entry: &'a AdbEntry, res: Resources<'a>) -> Result<(String, DisCode<'a>), DisError> {
+/// Geometry of a region object: the scene it belongs to, its base position,
+/// and the polygons making up the region.
+#[derive(Serialize)]
+pub struct DisRegion {
+    pub scene: String,
+    pub x: u16,
+    pub y: u16,
+    pub shapes: Vec<Vec<DisPoint>>,
+}
+
+#[derive(Serialize)]
+pub struct DisPoint {
+    pub x: u16,
+    pub y: u16,
+    pub z: Option<u16>,
+}
+
+/// Draws a region object as an SVG image, which has no plain text form.
+pub fn analyse_region<'a>(entry: &'a AdbEntry, res: Resources<'a>) -> Result<(DisRendered, DisCode<'a>, DisRegion), DisError> {
     let code = entry.raw();
     let mut output = DisCode::new(code, res.first_pass);
     if code.len() < 0x24 {
@@ -107,7 +127,7 @@ pub fn analyse_region<'a>(entry: &'a AdbEntry, res: Resources<'a>) -> Result<(St
 
     // header
     let name_end = code.iter().position(|b| *b == 0).unwrap_or(0x20);
-    let scene = crate::encoding::decode(&code[0..name_end]);
+    let scene = res.profile.encoding.decode(&code[0..name_end]);
     let scene_key = scene.replace(".", "");
     output.xrefs.push(AdbXref {
         other_key: scene_key.to_string(),
@@ -154,7 +174,11 @@ pub fn analyse_region<'a>(entry: &'a AdbEntry, res: Resources<'a>) -> Result<(St
                 } else {
                     output.line(pos - 6, pos, Some(format!("point {point_idx}: {pos_x}, {pos_y}, {pos_z}")), None, None);
                 }
-                points.push((pos_x, pos_y));
+                points.push(DisPoint {
+                    x: pos_x,
+                    y: pos_y,
+                    z: (pos_z != 0xCDCD).then_some(pos_z),
+                });
             }
             shapes.push(points);
         }
@@ -173,9 +197,9 @@ pub fn analyse_region<'a>(entry: &'a AdbEntry, res: Resources<'a>) -> Result<(St
     for (x, y, bg) in res.entries.get(&scene_key).and_then(|e| e.scene.as_ref()).into_iter().flat_map(|s| s.bg_reference.iter()) {
         svg.push_str(&format!("<image x=\"{x}\" y=\"{y}\" href=\"../../../exported/{bg}\"/>"));
     }
-    for (shape_idx, points) in shapes.into_iter().enumerate() {
+    for (shape_idx, points) in shapes.iter().enumerate() {
         svg.push_str("<path d=\"M");
-        for (idx, (x, y)) in points.into_iter().enumerate() {
+        for (idx, DisPoint { x, y, .. }) in points.iter().enumerate() {
             if idx != 0 {
                 svg.push('L');
             }
@@ -187,7 +211,12 @@ pub fn analyse_region<'a>(entry: &'a AdbEntry, res: Resources<'a>) -> Result<(St
     svg.push_str("</svg>");
 
     output.finalise();
-    Ok((svg, output))
+    Ok((DisRendered { html: svg, text: String::new() }, output, DisRegion {
+        scene: scene_key,
+        x: base_x,
+        y: base_y,
+        shapes,
+    }))
 }
 
 pub fn analyse_dialogue_text<'a>(raw: &'a [u8], res: Resources<'a>) -> Result<DisCode<'a>, DisError> {
@@ -200,7 +229,7 @@ pub fn analyse_dialogue_text<'a>(raw: &'a [u8], res: Resources<'a>) -> Result<Di
         if trimmed_line.ends_with(&[b'\r']) {
             trimmed_line = &trimmed_line[..trimmed_line.len() - 1];
         }
-        let line = crate::encoding::decode(trimmed_line);
+        let line = res.profile.encoding.decode(trimmed_line);
         if line.starts_with("@") {
             if line.starts_with("@@") {
                 output.line(pos, (pos + raw_line.len() + 1).min(raw.len()), None, Some(format!("<span class=\"hl-com\">{line}</span>")), None);
@@ -245,7 +274,7 @@ pub fn analyse_dialogue_text<'a>(raw: &'a [u8], res: Resources<'a>) -> Result<Di
 
 pub fn analyse_raw<'a>(code: &'a [u8], res: Resources<'a>) -> Result<DisCode<'a>, DisError> {
     let mut output = DisCode::new(code, res.first_pass);
-    let s = crate::encoding::decode(code);
+    let s = res.profile.encoding.decode(code);
     output.line(0, code.len(), None, Some(show_string(&s, res)), None);
     output.finalise();
     Ok(output)
@@ -259,9 +288,9 @@ pub fn analyse_string<'a>(raw: &'a [u8], res: Resources<'a>) -> Result<DisCode<'
     let mut raw_buf = raw.to_vec();
     dexor(&mut raw_buf[..]);
     if last_null || last_decoded_null {
-        decoded = crate::encoding::decode(&raw_buf[0..raw_buf.len() - 1]);
+        decoded = res.profile.encoding.decode(&raw_buf[0..raw_buf.len() - 1]);
     } else {
-        decoded = crate::encoding::decode(&raw_buf[..]);
+        decoded = res.profile.encoding.decode(&raw_buf[..]);
     }
     let mut output = DisCode::new(raw, res.first_pass);
     output.line(0, raw.len(), None, Some(show_string(&decoded, res)), None);
@@ -269,7 +298,7 @@ pub fn analyse_string<'a>(raw: &'a [u8], res: Resources<'a>) -> Result<DisCode<'
     Ok(output)
 }
 
-pub fn analyse_code<'a>(code: &'a [u8], res: Resources<'a>) -> Result<(Option<String>, DisCode<'a>), DisError> {
+pub fn analyse_code<'a>(code: &'a [u8], res: Resources<'a>) -> Result<(Option<DisRendered>, DisCode<'a>), DisError> {
     let mut output = DisCode::new(code, res.first_pass);
     if code.len() < 0x18 {
         return Err(DisError::TooShort);
@@ -304,7 +333,7 @@ pub fn analyse_code<'a>(code: &'a [u8], res: Resources<'a>) -> Result<(Option<St
         output.line(pos, pos, None, None, Some(format!("string pool start: {string_count} strings")));
         for string_idx in 0..string_count {
             let string_end = pos + code[pos..].iter().position(|b| *b == 0).expect("unterminated string");
-            let s = crate::encoding::decode(&code[pos..string_end]);
+            let s = res.profile.encoding.decode(&code[pos..string_end]);
             output.line(pos, string_end + 1, Some(show_string(&s, res)), None, Some(format!("string #{string_idx} / 0x{string_idx:02x}")));
             strings.push(s.to_string());
             pos = string_end + 1;
@@ -318,7 +347,7 @@ pub fn analyse_code<'a>(code: &'a [u8], res: Resources<'a>) -> Result<(Option<St
         output.line(code_start, code_start, None, None, Some("<span class=\"jump\"></span>code start".to_string()));
         match output.with_offset(code_start, |output| code::analyse(&code[code_start..code_end], code_start, &strings[..], res, output)) {
             Ok(pretty) => {
-                if !pretty.is_empty() {
+                if !pretty.html.is_empty() {
                     return Ok((Some(pretty), output));
                 }
             },
@@ -338,7 +367,7 @@ pub fn analyse_code<'a>(code: &'a [u8], res: Resources<'a>) -> Result<(Option<St
     Ok((None, output))
 }
 
-pub fn analyse_dummy<'a>(entry: &'a AdbEntry, res: Resources<'_>) -> Result<(Option<String>, DisCode<'a>), DisError> {
+pub fn analyse_dummy<'a>(entry: &'a AdbEntry, res: Resources<'_>) -> Result<(Option<DisRendered>, DisCode<'a>), DisError> {
     let mut output = DisCode::new(&[], res.first_pass);
     output.finalise();
     let mut pretty = None;
@@ -346,9 +375,10 @@ pub fn analyse_dummy<'a>(entry: &'a AdbEntry, res: Resources<'_>) -> Result<(Opt
         if let Some(global) = &entry.global {
             let mut values = global.values.iter().collect::<Vec<_>>();
             values.sort();
-            let mut lines = String::new();
+            let mut lines = DisRendered::default();
             for (key, value) in values {
-                lines.push_str(&format!("{key: <4} <span class=\"hl-com\">{value}</span>\n"));
+                lines.html.push_str(&format!("{key: <4} <span class=\"hl-com\">{value}</span>\n"));
+                lines.text.push_str(&format!("{key: <4} {value}\n"));
             }
             pretty = Some(lines);
         }
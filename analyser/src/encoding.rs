@@ -1,23 +1,29 @@
-use std::cell::Cell;
+/// Text encoding of the strings in .adb files.
+#[derive(Clone, Copy)]
+pub struct TextEncoding(&'static encoding_rs::Encoding);
 
-thread_local! {
-    pub static ENCODING: Cell<Option<&'static encoding_rs::Encoding>> = const { Cell::new(None) };
+impl Default for TextEncoding {
+    fn default() -> Self {
+        Self(encoding_rs::WINDOWS_1250)
+    }
 }
 
-pub fn set_encoding(label: &str) {
-    ENCODING.with(|c| {
-        c.set(Some(encoding_rs::Encoding::for_label(label.as_bytes())
-            .expect("no such encoding")));
-    });
-}
+impl TextEncoding {
+    pub fn for_label(label: &str) -> Option<Self> {
+        encoding_rs::Encoding::for_label(label.as_bytes()).map(Self)
+    }
 
-pub fn decode(data: &[u8]) -> String {
-    ENCODING.with(|c| {
-        let encoding = c.get()
-            .unwrap_or(encoding_rs::WINDOWS_1250);
-        encoding
+    pub fn decode(&self, data: &[u8]) -> String {
+        self.0
             .decode_without_bom_handling_and_without_replacement(data)
             .unwrap()
             .to_string()
-    })
+    }
+
+    /// Encodes the string, or returns `None` if it has characters the
+    /// encoding cannot represent.
+    pub fn encode(&self, s: &str) -> Option<Vec<u8>> {
+        let (data, _, unmappable) = self.0.encode(s);
+        (!unmappable).then(|| data.to_vec())
+    }
 }
@@ -9,15 +9,27 @@ fn trimnull(data: &[u8]) -> &[u8] {
     data
 }
 
-pub fn extract(
-    data: Vec<u8>,
-    filter: Option<HashSet<String>>,
-) -> impl Iterator<Item = (String, Vec<u8>)> {
-    const MAGIC1: &[u8] = b"AGDS group file\x1A";
-    const MAGIC2: &[u8] = b"\xE6\xC9\x03\x1A";
-    const VERSION1: u32 = 0x2C;
-    const VERSION2: u32 = 0x02;
+const MAGIC1: &[u8] = b"AGDS group file\x1A";
+const MAGIC2: &[u8] = b"\xE6\xC9\x03\x1A";
+const VERSION1: u32 = 0x2C;
+const VERSION2: u32 = 0x02;
+
+struct GrpHeader {
+    /// Raw archive header, kept to preserve unknown fields.
+    raw: Vec<u8>,
+    encrypted: bool,
+    files: Vec<GrpFileHeader>,
+}
 
+struct GrpFileHeader {
+    name: String,
+    /// Raw file header, kept to preserve unknown fields.
+    raw: Vec<u8>,
+    offset: usize,
+    size: usize,
+}
+
+fn read_header(data: &[u8]) -> GrpHeader {
     let header = data[0..0x2C].to_vec();
     let mut xor_buf = data[0..0x10].to_vec();
     dexor(&mut xor_buf);
@@ -30,8 +42,8 @@ pub fn extract(
     assert_eq!(version2, VERSION2, "version mismatch");
     let count = u32::from_le_bytes(header[0x1C..0x20].try_into().unwrap()) as usize;
 
-    (0..count)
-        .filter_map(move |file_idx| {
+    let files = (0..count)
+        .map(|file_idx| {
             let fhpos = header.len() + file_idx * 0x31;
             let file_header = &data[fhpos..fhpos + 0x31];
             let mut name_buf = trimnull(&file_header[0..0x21]).to_vec();
@@ -39,14 +51,163 @@ pub fn extract(
                 dexor(&mut name_buf);
             }
             let name = encoding_rs::WINDOWS_1250.decode_without_bom_handling_and_without_replacement(&name_buf).unwrap().to_string();
+            let offset = u32::from_le_bytes(file_header[0x21..0x25].try_into().unwrap()) as usize;
+            let size = u32::from_le_bytes(file_header[0x25..0x29].try_into().unwrap()) as usize;
+            GrpFileHeader {
+                name,
+                raw: file_header.to_vec(),
+                offset,
+                size,
+            }
+        })
+        .collect();
+    GrpHeader {
+        raw: header,
+        encrypted,
+        files,
+    }
+}
+
+/// Writes a .grp file. `header` is the raw archive header; the file count in
+/// it is updated. Each file is given with its raw file header (for the unknown
+/// fields, if any), its name, and its contents. Fails on names which cannot be
+/// stored.
+fn write(
+    mut header: Vec<u8>,
+    encrypted: bool,
+    files: Vec<(Option<Vec<u8>>, String, Vec<u8>)>,
+) -> Result<Vec<u8>, String> {
+    header[0x1C..0x20].copy_from_slice(&(files.len() as u32).to_le_bytes());
+    let mut data = header;
+
+    // file headers
+    let mut offset = data.len() + files.len() * 0x31;
+    for (raw, name, file) in &files {
+        if name.contains("..") || name.contains('/') || name.contains('\\') {
+            return Err(format!("invalid file name: {name}"));
+        }
+        let (name_buf, _, unmappable) = encoding_rs::WINDOWS_1250.encode(name);
+        if unmappable {
+            return Err(format!("cannot encode file name: {name}"));
+        }
+        if name_buf.len() > 0x21 {
+            return Err(format!("file name too long: {name}"));
+        }
+        let mut name_buf = name_buf.to_vec();
+        if encrypted {
+            dexor(&mut name_buf);
+            if name_buf.contains(&0) {
+                return Err(format!("cannot encrypt file name: {name}"));
+            }
+        }
+        name_buf.resize(0x21, 0);
+        data.extend_from_slice(&name_buf);
+        data.extend_from_slice(&(offset as u32).to_le_bytes());
+        data.extend_from_slice(&(file.len() as u32).to_le_bytes());
+        match raw {
+            Some(raw) => data.extend_from_slice(&raw[0x29..0x31]),
+            None => data.extend_from_slice(&[0; 8]),
+        }
+        offset += file.len();
+    }
+
+    // file contents
+    for (_, _, file) in files {
+        data.extend_from_slice(&file);
+    }
+    Ok(data)
+}
+
+pub fn extract(
+    data: Vec<u8>,
+    filter: Option<HashSet<String>>,
+) -> impl Iterator<Item = (String, Vec<u8>)> {
+    let header = read_header(&data);
+    header.files
+        .into_iter()
+        .filter_map(move |GrpFileHeader { name, offset, size, .. }| {
             if filter.as_ref().map(|f| !f.contains(&name)).unwrap_or(false) {
                 return None;
             }
             assert!(!name.contains(".."));
             assert!(!name.contains("/"));
             assert!(!name.contains("\\"));
-            let offset = u32::from_le_bytes(file_header[0x21..0x25].try_into().unwrap()) as usize;
-            let size = u32::from_le_bytes(file_header[0x25..0x29].try_into().unwrap()) as usize;
             Some((name, data[offset..offset + size].to_vec()))
         })
 }
+
+/// Builds a .grp file from the given files. When `encrypted` is set, the magic
+/// and the file names are XOR-encrypted, as in some of the original files.
+/// Fails on file names which cannot be stored.
+pub fn pack(
+    files: impl IntoIterator<Item = (String, Vec<u8>)>,
+    encrypted: bool,
+) -> Result<Vec<u8>, String> {
+    let mut header = Vec::new();
+    let mut magic = MAGIC1.to_vec();
+    if encrypted {
+        dexor(&mut magic);
+    }
+    header.extend_from_slice(&magic);
+    header.extend_from_slice(&VERSION1.to_le_bytes());
+    header.extend_from_slice(MAGIC2);
+    header.extend_from_slice(&VERSION2.to_le_bytes());
+    header.resize(0x2C, 0);
+    write(header, encrypted, files.into_iter().map(|(name, file)| (None, name, file)).collect())
+}
+
+/// Replaces, adds, or removes a single file in an existing .grp file. If
+/// `content` is `None`, the file is removed, otherwise it is replaced (or added
+/// at the end, if no file with the given name exists). File names are matched
+/// case-insensitively, and names which match more than one file are rejected.
+/// All other files keep their headers and contents, and the archive keeps its
+/// encryption state.
+pub fn replace(
+    data: Vec<u8>,
+    name: &str,
+    content: Option<Vec<u8>>,
+) -> Result<Vec<u8>, String> {
+    let header = read_header(&data);
+    let matches = header.files.iter()
+        .filter(|file| file.name.eq_ignore_ascii_case(name))
+        .count();
+    match matches {
+        0 if content.is_none() => return Err(format!("no such file: {name}")),
+        0 | 1 => (),
+        _ => return Err(format!("{matches} files match {name}")),
+    }
+    let mut files = Vec::new();
+    let mut content = content;
+    for GrpFileHeader { name: file_name, raw, offset, size } in header.files {
+        if !file_name.eq_ignore_ascii_case(name) {
+            files.push((Some(raw), file_name, data[offset..offset + size].to_vec()));
+        } else if let Some(content) = content.take() {
+            files.push((Some(raw), file_name, content));
+        }
+    }
+    if let Some(content) = content {
+        files.push((None, name.to_string(), content));
+    }
+    write(header.raw, header.encrypted, files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_rejects_bad_names() {
+        let files = || vec![("a.txt".to_string(), b"a".to_vec())];
+        for encrypted in [false, true] {
+            let grp = pack(files(), encrypted).unwrap();
+            let replaced = replace(grp.clone(), "b.txt", Some(b"b".to_vec())).unwrap();
+            assert_eq!(extract(replaced, None).count(), 2);
+            let long = "n".repeat(0x22);
+            for name in ["../b.txt", "dir/b.txt", "dir\\b.txt", "черн.txt", &long] {
+                assert!(replace(grp.clone(), name, Some(b"b".to_vec())).is_err(), "{name}");
+            }
+            // The first character encrypts to a null byte.
+            assert_eq!(replace(grp, "©.txt", Some(b"b".to_vec())).is_err(), encrypted);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use std::{collections::BTreeMap, sync::{atomic::{AtomicUsize, Ordering}, mpsc}};
+
+/// Number of jobs used when `--jobs` is not given: one per CPU.
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, |jobs| jobs.get())
+}
+
+/// Applies `f` to every item on `jobs` threads, and passes the results to
+/// `done` on the calling thread, in the order of the items. A result is passed
+/// as soon as the results of all items before it have been passed, so output
+/// printed by `done` is the same for any number of jobs.
+pub fn for_each_ordered<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync, mut done: impl FnMut(R)) {
+    if jobs <= 1 {
+        items.iter().for_each(|item| done(f(item)));
+        return;
+    }
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(items.len()) {
+            let tx = tx.clone();
+            let (next, f) = (&next, &f);
+            scope.spawn(move || loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(idx) else { break };
+                if tx.send((idx, f(item))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        let mut pending = BTreeMap::new();
+        let mut expected = 0;
+        for (idx, result) in rx {
+            pending.insert(idx, result);
+            while let Some(result) = pending.remove(&expected) {
+                done(result);
+                expected += 1;
+            }
+        }
+    });
+}
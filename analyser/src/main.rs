@@ -7,23 +7,30 @@
 #![feature(option_get_or_insert_default)]
 #![feature(round_char_boundary)]
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use sailfish::Template;
-use templates::nav::NavTree;
+use templates::{nav::NavTree, OutputFormat};
 use std::{collections::{HashMap, HashSet}, path::PathBuf};
 
 mod adb;
+mod asm;
+mod census;
 pub mod dis;
 pub mod encoding;
 mod grp;
+mod jobs;
 pub mod known;
 mod patches;
+mod script;
 mod templates;
+mod version;
 mod xor;
 
 use adb::{AdbEntry, AdbEntryKind, AdbXref, AdbXrefKind};
 
-use crate::dis::code::opcodes::set_opcode_map;
+use crate::dis::code::{opdb::OpcodeDb, opmap::{MapDeriver, OpcodeMap}, GraphFormat};
+use encoding::TextEncoding;
+use version::VersionProfile;
 
 pub const SDB: &str = "<span class=\"hl-dyn\">";
 pub const SCB: &str = "<span class=\"hl-com\">";
@@ -34,42 +41,262 @@ pub struct Resources<'a> {
     entries: &'a HashMap<String, AdbEntry>,
     data: &'a HashMap<String, (String, String, String)>,
     do_analyse: bool,
+    do_cfg: bool,
     first_pass: bool,
+    opcodes: &'a OpcodeDb,
+    profile: &'a VersionProfile,
 }
 
 
+/// Keys of the objects, in the order they are decompiled and reported.
+fn sorted_keys(entries: &HashMap<String, AdbEntry>) -> Vec<&String> {
+    let mut keys = entries.keys().collect::<Vec<_>>();
+    keys.sort();
+    keys
+}
+
+/// Objects decompiled in the second pass, by kind, and the code objects
+/// which could not be decompiled cleanly.
+#[derive(Default)]
+struct DecompileCounts {
+    string: usize,
+    raw: usize,
+    region: usize,
+    code: usize,
+    code_error: usize,
+    global: usize,
+    dummy: usize,
+    scene: usize,
+    unstructured: Vec<String>,
+    mistyped: Vec<String>,
+}
+
+impl DecompileCounts {
+    fn add(&mut self, other: Self) {
+        self.string += other.string;
+        self.raw += other.raw;
+        self.region += other.region;
+        self.code += other.code;
+        self.code_error += other.code_error;
+        self.global += other.global;
+        self.dummy += other.dummy;
+        self.scene += other.scene;
+        self.unstructured.extend(other.unstructured);
+        self.mistyped.extend(other.mistyped);
+    }
+}
+
+/// Help of the `--version` options, listing the built-in opcode maps.
+fn version_help(effect: &str) -> String {
+    format!(
+        "Sets the game version. {effect} Possible values: auto (default), {}, or the path to an opcode map file. \
+        `auto` detects the version of the .adb file (or uses {} without one)",
+        OpcodeMap::builtin_names().collect::<Vec<_>>().join(", "),
+        OpcodeMap::builtin_names().next().unwrap(),
+    )
+}
+
+/// Loads the version profile given by `--version`. When it is omitted or
+/// `auto`, the version of the .adb file is detected instead, or the default
+/// version is used without one. `encoding` (given by `--encoding`) replaces
+/// the text encoding of the profile. Errors are printed, and give `None`.
+fn select_profile(version: Option<&str>, db: Option<&[u8]>, encoding: Option<&str>) -> Option<VersionProfile> {
+    try_select_profile(version, db, encoding).map_err(|err| println!("{err}")).ok()
+}
+
+fn try_select_profile(version: Option<&str>, db: Option<&[u8]>, encoding: Option<&str>) -> Result<VersionProfile, String> {
+    let name = match (version.filter(|version| *version != "auto"), db) {
+        (Some(version), _) => version.to_string(),
+        (None, None) => OpcodeMap::builtin_names().next().ok_or("no built-in opcode maps")?.to_string(),
+        (None, Some(db)) => {
+            let detected = version::detect(db).map_err(|err| format!("cannot detect the version: {err}"))?;
+            let errors = detected.errors.iter()
+                .map(|(name, count)| format!("{name}: {count}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!("detected version {}, objects failing out of {} sampled: {errors}", detected.name, detected.sample);
+            detected.name.to_string()
+        }
+    };
+    let mut profile = VersionProfile::load(&name)?;
+    if let Some(label) = encoding {
+        profile.encoding = TextEncoding::for_label(label).ok_or_else(|| format!("no such encoding {label:?}"))?;
+    }
+    Ok(profile)
+}
+
 #[derive(Parser)]
 #[command(name = "re-posel-analyser")]
 #[command(about = "Analyser and patcher for Posel Smrti / Black Mirror game files.", long_about = None)]
 // #[command(version, about, long_about = None)]
 struct Cli {
     /// When provided, only the specified patches will be applied. Only has
-    /// effect when decompiling and creating patched .adb files.
+    /// effect when decompiling and creating patched .adb files, and only
+    /// patches for the selected game version are available.
     #[arg(long)]
     patch: Vec<String>,
 
     /// When provided, sets the text encoding used for string objects in .adb
-    /// files. Default: "windows-1250".
+    /// files. Default: the encoding of the game version, "windows-1250" if
+    /// it is not known.
     #[arg(long)]
     encoding: Option<String>,
 
+    /// Path to a JSON file with names, descriptions and argument labels of
+    /// unknown opcodes, keyed by mnemonic, e.g. `{"UnkC8": {"name":
+    /// "sample.volume", "description": "...", "args": ["channel",
+    /// "volume"]}}`. Used in decompiled code and in the opcode census.
+    #[arg(long)]
+    opcodes: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Option<CliCommand>,
 }
 
 #[derive(Subcommand)]
 enum CliCommand {
-    #[command(about = "Extract assets from a .grp file.", long_about = None)]
-    Extract {
-        /// Path to a .grp file.
+    #[command(about = "Extract assets from a .grp file, same as `grp extract`.", long_about = None)]
+    Extract(ExtractArgs),
+
+    #[command(about = "Extract, pack, or modify .grp files.", long_about = None)]
+    Grp {
+        #[command(subcommand)]
+        command: GrpCommand,
+    },
+
+    #[command(about = "Assemble or compile a code object from a text file.", long_about = None)]
+    Assemble {
+        /// Path to the assembly source.
         input: PathBuf,
 
-        /// When provided, only the specified filenames will be extracted.
         #[arg(long)]
-        name: Vec<String>,
+        #[arg(help = version_help("Affects the encoding of opcodes."))]
+        version: Option<String>,
 
-        /// Output path: a directory will be created at this path, if one does
-        /// not exist, and the selected assets will be extracted into it.
+        /// When provided, the input is a script in the dialect printed by the
+        /// decompiler, and is compiled before being assembled.
+        #[arg(long)]
+        script: bool,
+
+        /// When provided, only the raw code section will be written, without
+        /// the object header and the string pool.
+        #[arg(long)]
+        raw: bool,
+
+        /// Path to the original data.adb file. When provided, the assembled
+        /// object is stored in it under the key given by `--key`, replacing
+        /// the existing object (if any), and the output is a new .adb file.
+        /// The header fields and string pool metadata which are not
+        /// understood yet are copied from the replaced code object, or from
+        /// the one given by `--template`.
+        #[arg(long)]
+        #[arg(requires("key"))]
+        #[arg(conflicts_with("raw"))]
+        adb: Option<PathBuf>,
+
+        /// Key of the object to add or replace, when used with `--adb`.
+        #[arg(long)]
+        #[arg(requires("adb"))]
+        key: Option<String>,
+
+        /// Key of the code object to copy the fields which are not understood
+        /// yet from, when used with `--adb` and the key given by `--key` is
+        /// not a code object. Required in that case.
+        #[arg(long)]
+        #[arg(requires("adb"))]
+        template: Option<String>,
+
+        /// Path to target file.
+        output: PathBuf,
+    },
+
+    #[command(about = "Check that the code and strings of code objects can be disassembled and reassembled byte-for-byte.", long_about = None)]
+    VerifyRoundtrip {
+        /// Path to a data.adb file. The first value is the game version (e.g.,
+        /// "1.0en", or the path to an opcode map file), the second is the path
+        /// to the file. Can be provided multiple times; results are summarised
+        /// per version.
+        #[arg(long)]
+        #[arg(num_args(2..=2))]
+        #[arg(required(true))]
+        adb: Vec<PathBuf>,
+
+        /// When provided, only the given objects will be checked. This value
+        /// is a regular expression.
+        #[arg(long)]
+        filter: Option<String>,
+
+        /// Output path: when provided, a directory will be created at this
+        /// path, if one does not exist, and the disassembly of every checked
+        /// object will be written into it.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    #[command(about = "Measure how long decompiling the largest code objects takes.", long_about = None)]
+    Benchmark {
+        /// Path to the original data.adb file.
+        input: PathBuf,
+
+        #[arg(long)]
+        #[arg(help = version_help("Affects decompilation of code objects."))]
+        version: Option<String>,
+
+        /// Number of code objects to decompile, largest first. Default: 10
+        #[arg(long)]
+        count: Option<usize>,
+
+        /// Number of times every object is decompiled. The fastest run is
+        /// reported. Default: 5
+        #[arg(long)]
+        runs: Option<usize>,
+    },
+
+    #[command(about = "Count the uses of opcodes, with statistics on their arguments.", long_about = None)]
+    Opcodes {
+        /// Path to the original data.adb file.
+        input: PathBuf,
+
+        #[arg(long)]
+        #[arg(help = version_help("Affects the encoding of opcodes."))]
+        version: Option<String>,
+
+        /// When provided, only the given objects will be counted. This value
+        /// is a regular expression.
+        #[arg(long)]
+        filter: Option<String>,
+
+        /// When provided, every opcode will be reported, not only the unknown
+        /// (`Unk*`) ones.
+        #[arg(long)]
+        all: bool,
+
+        /// Number of most common values listed for every statistic (objects,
+        /// immediates, arguments, neighbouring opcodes). Default: 5
+        #[arg(long)]
+        top: Option<usize>,
+    },
+
+    #[command(about = "Derive the opcode map of a new game version from equivalent code objects.", long_about = None)]
+    DeriveMap {
+        /// Path to a data.adb file of a game version with a known opcode map.
+        known: PathBuf,
+
+        #[arg(long)]
+        #[arg(help = version_help("Selects the opcode map of the known file."))]
+        version: Option<String>,
+
+        /// Path to the data.adb file of the new game version.
+        new: PathBuf,
+
+        /// Key of an object which has the same code in both files, up to the
+        /// encoding of opcodes. Can be provided multiple times. When omitted,
+        /// every code object with the same key and code size in both files is
+        /// tried, and those which do not match are skipped.
+        #[arg(long)]
+        key: Vec<String>,
+
+        /// Path to the opcode map file to write.
         output: PathBuf,
     },
 
@@ -84,9 +311,8 @@ enum CliCommand {
         #[arg(num_args(2..=2))]
         group: Vec<PathBuf>,
 
-        /// Sets the game version. Affects decompilation of code objects.
-        /// Possible values: 1.0en (default), 1.0pl, 1.03bu
         #[arg(long)]
+        #[arg(help = version_help("Affects decompilation of code objects."))]
         version: Option<String>,
 
         /// When provided, only the given objects will be decompiled. This
@@ -95,16 +321,21 @@ enum CliCommand {
         filter: Option<String>,
 
         /// When provided, bytecode will be decompiled into readable script.
-        /// This may be slow on larger objects.
         #[arg(long)]
         analyse: bool,
 
+        /// When provided, the control-flow graph of each code object will be
+        /// written next to it, and embedded into its HTML page.
+        #[arg(long, value_enum)]
+        cfg: Option<GraphFormat>,
+
         /// When provided, cross references will be identified.
         #[arg(long)]
         crossref: bool,
 
-        /// When provided, known objects will be labelled. (Only works for the
-        /// English version `1.0en`.)
+        /// When provided, known objects will be labelled. (Only works for
+        /// versions with known labels, currently the English version
+        /// `1.0en`.)
         #[arg(long)]
         apply_known: bool,
 
@@ -112,6 +343,17 @@ enum CliCommand {
         #[arg(long)]
         dryrun: bool,
 
+        /// Number of objects analysed and rendered at the same time. The
+        /// output does not depend on it. Default: the number of CPUs
+        #[arg(long)]
+        jobs: Option<usize>,
+
+        /// Sets the format of the decompiled objects. `text` and `md` write
+        /// one readable file per object, without the navigation and assets.
+        /// `json` writes all selected objects into a single `database.json`.
+        #[arg(long, value_enum, default_value = "html")]
+        format: OutputFormat,
+
         /// Output path: a directory will be created at this path, if one does
         /// not exist, and the selected objects will be decompiled into it.
         output: PathBuf,
@@ -122,11 +364,73 @@ enum CliCommand {
         /// Path to original data.adb file.
         input: PathBuf,
 
+        #[arg(long)]
+        #[arg(help = version_help("Selects the patches which apply."))]
+        version: Option<String>,
+
         /// Path to target data.adb file. Cannot be the same as input.
         output: PathBuf,
     },
 }
 
+#[derive(Args)]
+struct ExtractArgs {
+    /// Path to a .grp file.
+    input: PathBuf,
+
+    /// When provided, only the specified filenames will be extracted.
+    #[arg(long)]
+    name: Vec<String>,
+
+    /// Output path: a directory will be created at this path, if one does
+    /// not exist, and the selected assets will be extracted into it.
+    output: PathBuf,
+}
+
+#[derive(Subcommand)]
+enum GrpCommand {
+    #[command(about = "Extract assets from a .grp file.", long_about = None)]
+    Extract(ExtractArgs),
+
+    #[command(about = "Create a .grp file from a directory of assets.", long_about = None)]
+    Pack {
+        /// Path to a directory: every file in it will be added to the .grp
+        /// file.
+        input: PathBuf,
+
+        /// When provided, the .grp header and file names will be encrypted.
+        #[arg(long)]
+        encrypted: bool,
+
+        /// Path to target .grp file.
+        output: PathBuf,
+    },
+
+    #[command(about = "Replace, add, or remove a single asset in a .grp file.", long_about = None)]
+    Replace {
+        /// Path to the original .grp file.
+        input: PathBuf,
+
+        /// Name of the asset to replace, add, or remove.
+        #[arg(long)]
+        name: String,
+
+        /// Path to the new content of the asset. If there is no asset with the
+        /// given name, it will be added.
+        #[arg(long)]
+        #[arg(required_unless_present("remove"))]
+        with: Option<PathBuf>,
+
+        /// When provided, the asset will be removed instead.
+        #[arg(long)]
+        #[arg(conflicts_with("with"))]
+        remove: bool,
+
+        /// Path to target .grp file.
+        output: PathBuf,
+    },
+}
+
 fn main() {
     let cli = Cli::parse();
     let Some(command) = cli.command else {
@@ -134,13 +438,23 @@ fn main() {
         return;
     };
 
-    // Set text encoding.
-    if let Some(label) = cli.encoding {
-        encoding::set_encoding(&label);
-    }
+    let encoding = cli.encoding.as_deref();
+
+    // Load opcode labels.
+    let opcodes = match cli.opcodes {
+        Some(path) => match OpcodeDb::parse(&std::fs::read_to_string(&path).unwrap()) {
+            Ok(opcodes) => opcodes,
+            Err(err) => {
+                println!("cannot load opcode labels from {path:?}: {err}");
+                return;
+            }
+        },
+        None => OpcodeDb::default(),
+    };
 
     // For extraction, we don't need an ADB input.
-    if let CliCommand::Extract { input, name, output } = command {
+    if let CliCommand::Extract(ExtractArgs { input, name, output })
+    | CliCommand::Grp { command: GrpCommand::Extract(ExtractArgs { input, name, output }) } = command {
         println!("extracting .grp file {input:?} into {output:?} ...");
         let grp = std::fs::read(input).unwrap();
         std::fs::create_dir_all(&output).unwrap();
@@ -155,42 +469,366 @@ fn main() {
         return;
     }
 
-    // Prepare the selected patches.
+    // Packing does not need an ADB input either.
+    if let CliCommand::Grp { command: GrpCommand::Pack { input, encrypted, output } } = command {
+        println!("packing directory {input:?} into {output:?} ...");
+        let mut files = Vec::new();
+        for file in std::fs::read_dir(&input).unwrap() {
+            let file = file.unwrap();
+            if !file.file_type().unwrap().is_file() {
+                continue;
+            }
+            let name = match file.file_name().into_string() {
+                Ok(name) => name,
+                Err(name) => {
+                    println!("cannot pack {name:?}: file name is not valid UTF-8");
+                    return;
+                }
+            };
+            let data = std::fs::read(file.path()).unwrap();
+            files.push((name, data));
+        }
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (name, data) in &files {
+            println!("  {name} ({} bytes) ...", data.len());
+        }
+        let packed = match grp::pack(files, encrypted) {
+            Ok(packed) => packed,
+            Err(err) => {
+                println!("cannot pack {input:?}: {err}");
+                return;
+            }
+        };
+        std::fs::write(&output, packed).unwrap();
+        println!(".grp file written to {output:?}");
+        return;
+    }
+
+    // Neither does replacing assets.
+    if let CliCommand::Grp { command: GrpCommand::Replace { input, name, with, output, .. } } = command {
+        let grp = std::fs::read(input).unwrap();
+        let content = with.map(|path| std::fs::read(path).unwrap());
+        match &content {
+            Some(data) => println!("replacing {name} ({} bytes) ...", data.len()),
+            None => println!("removing {name} ..."),
+        }
+        let replaced = match grp::replace(grp, &name, content) {
+            Ok(replaced) => replaced,
+            Err(err) => {
+                println!("cannot replace {name}: {err}");
+                return;
+            }
+        };
+        std::fs::write(&output, replaced).unwrap();
+        println!(".grp file written to {output:?}");
+        return;
+    }
+
+    // Assembling does not need an ADB input, unless inserting the result.
+    if let CliCommand::Assemble { input, version, script, raw, adb, key, template, output } = command {
+        if let Some(key) = &key && (key.len() > 32 || key.contains('\0')) {
+            println!("invalid key {key:?}: keys are at most 32 bytes long, without null bytes");
+            return;
+        }
+        let db = adb.map(|adb| std::fs::read(adb).unwrap());
+        let Some(profile) = select_profile(version.as_deref(), db.as_deref(), encoding) else { return };
+        let mut source = std::fs::read_to_string(&input).unwrap();
+        if script {
+            source = match script::compile(&source) {
+                Ok(compiled) => compiled,
+                Err(err) => {
+                    println!("cannot compile {input:?}: {err:?}");
+                    return;
+                }
+            };
+        }
+        let assembled = match asm::assemble(&source, &profile.map) {
+            Ok(assembled) => assembled,
+            Err(err) => {
+                println!("cannot assemble {input:?}: {err:?}");
+                return;
+            }
+        };
+        println!("{} bytes of code", assembled.code.len());
+        let mut labels = assembled.labels.iter().collect::<Vec<_>>();
+        labels.sort_by_key(|(label, pos)| (**pos, label.to_string()));
+        for (label, pos) in labels {
+            println!("  {label}: {pos:04x}");
+        }
+        println!("string pool: {} strings", assembled.strings.len());
+        for (idx, s) in assembled.strings.iter().enumerate() {
+            println!("  #{idx} / 0x{idx:02x}: {s:?}");
+        }
+        if raw {
+            std::fs::write(&output, assembled.code).unwrap();
+            println!("code section written to {output:?}");
+        } else if let (Some(db), Some(key)) = (db, key) {
+            let mut entries = adb::extract(db).collect::<Vec<_>>();
+            let template_key = template.as_ref().unwrap_or(&key);
+            let template = entries.iter()
+                .find(|(other_key, _)| other_key == template_key)
+                .and_then(|(_, entry)| match &entry.kind {
+                    AdbEntryKind::Code(object) => Some(object),
+                    _ => None,
+                });
+            let Some(template) = template else {
+                println!("{template_key} is not a code object, give an existing one with --template");
+                return;
+            };
+            let built = match assembled.build_object(Some(template), profile.encoding) {
+                Ok(built) => built,
+                Err(err) => {
+                    println!("cannot build code object: {err:?}");
+                    return;
+                }
+            };
+            let object = match entries.iter_mut().find(|(other_key, _)| *other_key == key) {
+                Some((_, entry)) => {
+                    println!("replacing object {key}");
+                    entry.kind = AdbEntryKind::Code(built);
+                    entry
+                }
+                None => {
+                    println!("adding object {key}");
+                    entries.push((key.clone(), AdbEntry::new(AdbEntryKind::Code(built))));
+                    &entries.last().unwrap().1
+                }
+            };
+            println!("code object: {} bytes", object.size());
+            std::fs::write(&output, adb::create(entries.iter().map(|(key, entry)| (key.as_str(), entry)))).unwrap();
+            println!(".adb file written to {output:?}");
+        } else {
+            let object = match assembled.build_object(None, profile.encoding) {
+                Ok(object) => object,
+                Err(err) => {
+                    println!("cannot build code object: {err:?}");
+                    return;
+                }
+            };
+            println!("code object: {} bytes, without a template: unknown fields have default values", object.len());
+            std::fs::write(&output, object).unwrap();
+            println!("code object written to {output:?}");
+        }
+        return;
+    }
+
+    // Round-trip verification reads its own ADB inputs.
+    if let CliCommand::VerifyRoundtrip { adb, filter, output } = command {
+        let entry_filter = filter.map(|pat| regex::Regex::new(&pat).unwrap());
+        let mut summary = Vec::new();
+        assert_eq!(adb.len() % 2, 0);
+        for [version, path] in adb.into_iter().array_chunks() {
+            let db = std::fs::read(&path).unwrap();
+            let Some(profile) = select_profile(Some(&version.to_string_lossy()), Some(&db), encoding) else { return };
+            let version = profile.name.clone();
+            println!("verifying {path:?} ({version}) ...");
+            let mut count_code = 0;
+            let mut count_identical = 0;
+            let mut count_undecoded = 0;
+            let mut count_undecoded_bytes = 0;
+            let mut count_mismatch = 0;
+            let mut count_failed = 0;
+            let mut entries = adb::extract(db).collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (key, entry) in &entries {
+                let AdbEntryKind::Code(object) = &entry.kind else { continue; };
+                if let Some(re) = entry_filter.as_ref() {
+                    if !re.is_match(key) {
+                        continue;
+                    }
+                }
+                count_code += 1;
+                let (result, source) = asm::verify_roundtrip(object, &profile);
+                match result {
+                    asm::RoundtripResult::Identical { undecoded: 0 } => count_identical += 1,
+                    asm::RoundtripResult::Identical { undecoded } => {
+                        count_undecoded += 1;
+                        count_undecoded_bytes += undecoded;
+                    }
+                    asm::RoundtripResult::Mismatch { offset } => {
+                        println!("  {key}: mismatch at 0x{offset:04x}");
+                        count_mismatch += 1;
+                    }
+                    asm::RoundtripResult::Failed(err) => {
+                        println!("  {key}: {err}");
+                        count_failed += 1;
+                    }
+                }
+                if let (Some(output), Some(source)) = (output.as_ref(), source) {
+                    let mut output = output.join(&version);
+                    std::fs::create_dir_all(&output).unwrap();
+                    output.push(format!("{key}.asm"));
+                    std::fs::write(&output, source).unwrap();
+                }
+            }
+            summary.push((version, count_code, count_identical, count_undecoded, count_undecoded_bytes, count_mismatch, count_failed));
+        }
+        for (version, count_code, count_identical, count_undecoded, count_undecoded_bytes, count_mismatch, count_failed) in summary {
+            println!("{version}:");
+            println!("  code:       {count_code}");
+            println!("  identical:  {count_identical} (code sections and strings, other fields are copied)");
+            println!("  undecoded:  {count_undecoded} ({count_undecoded_bytes} bytes as raw data)");
+            println!("  mismatched: {count_mismatch}");
+            println!("  failed:     {count_failed}");
+        }
+        return;
+    }
+
+    // Benchmarking reads its own ADB input too.
+    if let CliCommand::Benchmark { input, version, count, runs } = command {
+        let db = std::fs::read(&input).unwrap();
+        let Some(profile) = select_profile(version.as_deref(), Some(&db), encoding) else { return };
+        let entries = adb::extract(db).collect::<HashMap<_, _>>();
+        let data = HashMap::new();
+        let res = Resources {
+            entries: &entries,
+            data: &data,
+            do_analyse: true,
+            do_cfg: false,
+            first_pass: false,
+            opcodes: &opcodes,
+            profile: &profile,
+        };
+        let mut objects = entries.iter()
+            .filter_map(|(key, entry)| match &entry.kind {
+                AdbEntryKind::Code(c) => Some((key, c)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        objects.sort_by(|(a_key, a), (b_key, b)| b.len().cmp(&a.len()).then(a_key.cmp(b_key)));
+        objects.truncate(count.unwrap_or(10));
+        let runs = runs.unwrap_or(5).max(1);
+        println!("decompiling {} largest code objects, {runs} runs each ...", objects.len());
+        let mut total = std::time::Duration::ZERO;
+        for (key, c) in objects {
+            let mut best = std::time::Duration::MAX;
+            let mut result = "ok";
+            for _ in 0..runs {
+                let start = std::time::Instant::now();
+                result = match dis::analyse_code(c, res) {
+                    Ok((_, code)) if !code.unstructured.is_empty() => "unstructured",
+                    Ok(_) => "ok",
+                    Err(_) => "errored",
+                };
+                best = best.min(start.elapsed());
+            }
+            total += best;
+            println!("  {key:32} {:6} bytes {:10.3} ms  {result}", c.len(), best.as_secs_f64() * 1000.0);
+        }
+        println!("total: {:.3} ms", total.as_secs_f64() * 1000.0);
+        return;
+    }
+
+    // So does the opcode census.
+    if let CliCommand::Opcodes { input, version, filter, all, top } = command {
+        let db = std::fs::read(&input).unwrap();
+        let Some(profile) = select_profile(version.as_deref(), Some(&db), encoding) else { return };
+        let entry_filter = filter.map(|pat| regex::Regex::new(&pat).unwrap());
+        let mut entries = adb::extract(db).collect::<Vec<_>>();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut census = census::OpcodeCensus::new(&profile.map);
+        for (key, entry) in &entries {
+            let AdbEntryKind::Code(object) = &entry.kind else { continue; };
+            if entry_filter.as_ref().is_some_and(|re| !re.is_match(key)) {
+                continue;
+            }
+            if let Err(err) = census.add_object(key, object) {
+                println!("  {key}: {err:?}");
+            }
+        }
+        print!("{}", census.report(&opcodes, all, top.unwrap_or(5)));
+        return;
+    }
+
+    // Deriving an opcode map reads two ADB inputs.
+    if let CliCommand::DeriveMap { known, version, new, key, output } = command {
+        let known_db = std::fs::read(&known).unwrap();
+        let Some(profile) = select_profile(version.as_deref(), Some(&known_db), encoding) else { return };
+        let known_entries = adb::extract(known_db).collect::<HashMap<_, _>>();
+        let new_entries = adb::extract(std::fs::read(&new).unwrap()).collect::<HashMap<_, _>>();
+        let mut keys = if key.is_empty() {
+            known_entries.keys().filter(|key| new_entries.contains_key(*key)).cloned().collect::<Vec<_>>()
+        } else {
+            key
+        };
+        keys.sort();
+        let mut deriver = MapDeriver::new(&profile.map);
+        let mut count_pairs = 0;
+        for key in &keys {
+            let (Some(known_entry), Some(new_entry)) = (known_entries.get(key), new_entries.get(key)) else {
+                println!("  {key}: not in both files");
+                continue;
+            };
+            let (AdbEntryKind::Code(known_object), AdbEntryKind::Code(new_object)) = (&known_entry.kind, &new_entry.kind) else {
+                continue;
+            };
+            let result = asm::code_section(known_object)
+                .and_then(|known_code| Ok((known_code, asm::code_section(new_object)?)))
+                .map_err(|err| format!("{err:?}"))
+                .and_then(|(known_code, new_code)| deriver.add_pair(known_code, new_code));
+            match result {
+                Ok(_) => count_pairs += 1,
+                Err(err) => println!("  {key}: skipped, {err}"),
+            }
+        }
+        let derived = deriver.finish();
+        println!("{count_pairs} equivalent objects, {} bytes observed, {} inferred", derived.observed, derived.inferred);
+        let mut header = format!(
+            "Derived from {count_pairs} objects of {new:?}, compared to {known:?} ({}).",
+            profile.name,
+        );
+        if !derived.unknown.is_empty() {
+            let unknown = derived.unknown.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" ");
+            println!("unknown bytes: {unknown}");
+            header.push_str(&format!("\nUnknown bytes, given the leftover opcodes: {unknown}"));
+        }
+        std::fs::write(&output, derived.map.to_source(&header)).unwrap();
+        println!("opcode map written to {output:?}");
+        return;
+    }
+
+    // Read .adb file.
+    let (db_path, version) = match &command {
+        CliCommand::Decompile { input, version, .. }
+        | CliCommand::Patch { input, version, .. } => (input.clone(), version.clone()),
+        _ => unreachable!(),
+    };
+    let db = std::fs::read(&db_path).unwrap();
+    let Some(profile) = select_profile(version.as_deref(), Some(&db), encoding) else { return };
+
+    // Prepare the selected patches of this version.
     let mut patcher = patches::Patcher::new();
     let mut patch_count = 0;
     let patch_filter = (!cli.patch.is_empty()).then(|| cli.patch.into_iter().collect::<HashSet<String>>());
-    for patch in patches::ACTIVE_PATCHES {
+    for patch in profile.patches {
         if patch_filter.as_ref().map(|f| !f.contains(patch.name)).unwrap_or(false) {
             continue;
         }
         patcher.add_patch(patch);
         patch_count += 1;
     }
+    for name in patch_filter.iter().flatten() {
+        if !profile.patches.iter().any(|patch| patch.name == name) {
+            println!("patch {name} does not apply to version {}", profile.name);
+        }
+    }
     println!("{patch_count} patches ready");
 
-    // Read .adb file.
-    let db_path = match &command {
-        CliCommand::Decompile { input, .. }
-        | CliCommand::Patch { input, .. } => input.clone(),
-        _ => unreachable!(),
-    };
-    let db = std::fs::read(&db_path).unwrap();
-
     match command {
         CliCommand::Decompile {
             group,
-            version,
             filter,
             output,
             analyse: do_analyse,
             crossref: do_xref,
             apply_known: do_apply_known,
             dryrun,
+            jobs,
+            format,
+            cfg,
             ..
         } => {
-            if let Some(version) = version {
-                set_opcode_map(&version);
-            }
+            let jobs = jobs.unwrap_or_else(jobs::default_jobs);
 
             // Discard patches if not applying to known version.
             if !do_apply_known {
@@ -220,35 +858,41 @@ fn main() {
                     entries: &entries,
                     data: &data,
                     do_analyse: false,
+                    do_cfg: false,
                     first_pass: true,
+                    opcodes: &opcodes,
+                    profile: &profile,
                 };
                 let mut xrefs = Vec::new();
-                for (key, entry) in &entries {
-                    xrefs.extend(match &entry.kind {
-                        AdbEntryKind::Code(c) => {
-                            patcher.with_data(key, c, |c, _patches| {
-                                dis::analyse_code(c, res).unwrap().1.finalise_xrefs()
-                            })
-                        }
-                        _ => continue,
-                        /*
-                        AdbEntryKind::String { raw, decoded, .. } => {
-                            dis::analyse_string(raw, decoded, res).unwrap();
-                        }
-                        AdbEntryKind::Raw(c) => {
-                            if entry.region.is_some() || key.ends_with(".r") || key.ends_with(".rp") {
-                                dis::analyse_region(entry, res).unwrap();
-                            } else {
-                                dis::analyse_raw(c, res).unwrap();
+                jobs::for_each_ordered(&sorted_keys(&entries), jobs, |key| {
+                        let found = match &entries[*key].kind {
+                            AdbEntryKind::Code(c) => {
+                                patcher.with_data(key, c, |c, _patches| {
+                                    dis::analyse_code(c, res).map(|(_, code)| code.finalise_xrefs()).unwrap_or_default()
+                                })
                             }
-                        }
-                        AdbEntryKind::Dummy
-                        | AdbEntryKind::Global => {
-                            dis::analyse_dummy(res).unwrap();
-                        }
-                        */
-                    }.into_iter().map(|xref| (key.to_string(), xref)));
-                }
+                            _ => Vec::new(),
+                            /*
+                            AdbEntryKind::String { raw, decoded, .. } => {
+                                dis::analyse_string(raw, decoded, res).unwrap();
+                            }
+                            AdbEntryKind::Raw(c) => {
+                                if entry.region.is_some() || key.ends_with(".r") || key.ends_with(".rp") {
+                                    dis::analyse_region(entry, res).unwrap();
+                                } else {
+                                    dis::analyse_raw(c, res).unwrap();
+                                }
+                            }
+                            AdbEntryKind::Dummy
+                            | AdbEntryKind::Global => {
+                                dis::analyse_dummy(res).unwrap();
+                            }
+                            */
+                        };
+                        found.into_iter().map(|xref| (key.to_string(), xref)).collect::<Vec<_>>()
+                    },
+                    |found| xrefs.extend(found),
+                );
 
                 // Process cross references from code objects.
                 for (from, xref) in xrefs {
@@ -277,17 +921,24 @@ fn main() {
                     entries: &entries,
                     data: &data,
                     do_analyse: false,
+                    do_cfg: false,
                     first_pass: true,
+                    opcodes: &opcodes,
+                    profile: &profile,
                 };
                 let mut xrefs = Vec::new();
-                for (key, entry) in &entries {
-                    xrefs.extend(match &entry.kind {
-                        AdbEntryKind::Raw(..) if entry.is_region(key) => {
-                            dis::analyse_region(entry, res).unwrap().1.finalise_xrefs()
-                        }
-                        _ => continue,
-                    }.into_iter().map(|xref| (key.to_string(), xref)));
-                }
+                jobs::for_each_ordered(&sorted_keys(&entries), jobs, |key| {
+                        let entry = &entries[*key];
+                        let found = match &entry.kind {
+                            AdbEntryKind::Raw(..) if entry.is_region(key) => {
+                                dis::analyse_region(entry, res).unwrap().1.finalise_xrefs()
+                            }
+                            _ => Vec::new(),
+                        };
+                        found.into_iter().map(|xref| (key.to_string(), xref)).collect::<Vec<_>>()
+                    },
+                    |found| xrefs.extend(found),
+                );
 
                 // Process cross references from code objects.
                 // TODO: code duplication
@@ -318,16 +969,6 @@ fn main() {
                 }
             }
 
-            /*
-            // Opcode statistics.
-            println!("opcode stats:");
-            let stats = crate::dis::code::opcodes::stats();
-            for i in 0..256 {
-                println!("  - {i:02x}: {}", stats[i]);
-            }
-            return;
-            */
-
             // Create hierarchy.
             let mut root = NavTree {
                 key: "".to_string(),
@@ -341,7 +982,10 @@ fn main() {
 
             // Apply known labels to objects.
             if do_apply_known {
-                known::apply_known(&mut root, &mut entries);
+                match profile.labels {
+                    Some(labels) => labels(&mut root, &mut entries),
+                    None => println!("no known labels for version {}", profile.name),
+                }
             }
 
             // Second pass: output decompiled objects.
@@ -352,34 +996,34 @@ fn main() {
                 entries: &entries,
                 data: &data,
                 do_analyse,
+                do_cfg: cfg.is_some(),
                 first_pass: false,
+                opcodes: &opcodes,
+                profile: &profile,
             };
 
-            // Produce walkthrough.
-            for (idx, steps) in known::create_walkthrough(res) {
-                output.push(format!("walkthrough.{idx}.html"));
-                std::fs::write(&output, templates::Walkthrough {
-                    title: format!("walkthrough.{idx}"),
-                    steps,
-                }.render().unwrap()).unwrap();
-                output.pop();
+            // Produce walkthrough (only linked from HTML output).
+            if format == OutputFormat::Html {
+                for (idx, steps) in known::create_walkthrough(res) {
+                    output.push(format!("walkthrough.{idx}.html"));
+                    std::fs::write(&output, templates::Walkthrough {
+                        title: format!("walkthrough.{idx}"),
+                        steps,
+                    }.render().unwrap()).unwrap();
+                    output.pop();
+                }
             }
 
-            let mut count_string = 0;
-            let mut count_raw = 0;
-            let mut count_region = 0;
-            let mut count_code = 0;
-            let mut count_code_error = 0;
-            let mut count_global = 0;
-            let mut count_dummy = 0;
-            let mut count_scene = 0;
             let entry_filter = filter.map(|pat| regex::Regex::new(&pat).unwrap());
-            for (key, entry) in &entries {
-                if let Some(re) = entry_filter.as_ref() {
-                    if !re.is_match(key) {
-                        continue;
-                    }
-                }
+            let keys = sorted_keys(&entries).into_iter()
+                .filter(|key| entry_filter.as_ref().map_or(true, |re| re.is_match(key)))
+                .collect::<Vec<_>>();
+            let mut counts = DecompileCounts::default();
+            let mut json_objects = std::collections::BTreeMap::new();
+            jobs::for_each_ordered(&keys, jobs, |key| {
+                let entry = &entries[*key];
+                let mut counts = DecompileCounts::default();
+                let mut log = String::new();
                 let mut prefix_full = String::new();
                 let key_parts = key.split('.').collect::<Vec<_>>();
                 let rendered_breadcrumbs = key_parts.iter()
@@ -395,88 +1039,155 @@ fn main() {
                         res
                     })
                     .collect();
-                println!("  {key} ({}, {} bytes)", entry.describe(key), entry.size());
+                log.push_str(&format!("  {key} ({}, {} bytes)\n", entry.describe(key), entry.size()));
                 let mut pretty = None;
+                let mut region = None;
                 let code = match &entry.kind {
                     AdbEntryKind::String { raw, .. } if entry.is_dialogue_text() => {
-                        count_string += 1; // TODO
+                        counts.string += 1; // TODO
                         dis::analyse_dialogue_text(raw, res).unwrap()
                     }
                     AdbEntryKind::String { raw, .. } => {
-                        count_string += 1;
+                        counts.string += 1;
                         dis::analyse_string(raw, res).unwrap()
                     }
                     AdbEntryKind::Raw(raw) if entry.is_text() || entry.is_dialogue_text() => {
-                        count_string += 1;
+                        counts.string += 1;
                         dis::analyse_string(raw, res).unwrap()
                     }
                     AdbEntryKind::Raw(_) if entry.is_region(key) => {
-                        count_region += 1;
-                        let (p, code) = dis::analyse_region(entry, res).unwrap();
+                        counts.region += 1;
+                        let (p, code, r) = dis::analyse_region(entry, res).unwrap();
                         pretty = Some(p);
+                        region = Some(r);
                         code
                     }
                     AdbEntryKind::Raw(c) => {
-                        count_raw += 1;
+                        counts.raw += 1;
                         //dis::analyse_raw(c, res).unwrap()
                         dis::analyse_string(c, res).unwrap()
                     }
                     AdbEntryKind::Code(c) => {
-                        count_code += 1;
+                        counts.code += 1;
                         patcher.with_data(key, c, |c, patches| {
-                            let (p, code) = dis::analyse_code(c, res).unwrap();
+                            let (p, code) = match dis::analyse_code(c, res) {
+                                Ok(v) => v,
+                                Err(err) => {
+                                    // Show the object as raw data instead.
+                                    log.push_str(&format!("    cannot analyse: {err:?}\n"));
+                                    counts.code_error += 1;
+                                    return dis::analyse_string(c, res).unwrap().finalise_with_patches(patches);
+                                }
+                            };
                             pretty = p;
                             if code.error {
-                                println!("    code error!");
-                                count_code_error += 1;
+                                log.push_str("    code error!\n");
+                                counts.code_error += 1;
+                            }
+                            if !code.unstructured.is_empty() {
+                                log.push_str(&format!("    {} unstructured region(s), printed with gotos\n", code.unstructured.len()));
+                                for reason in &code.unstructured {
+                                    log.push_str(&format!("      {reason}\n"));
+                                }
+                                counts.unstructured.push(key.to_string());
+                            }
+                            if !code.diagnostics.is_empty() {
+                                log.push_str(&format!("    {} stack type diagnostic(s)\n", code.diagnostics.len()));
+                                for diagnostic in &code.diagnostics {
+                                    log.push_str(&format!("      {diagnostic}\n"));
+                                }
+                                counts.mistyped.push(key.to_string());
                             }
                             code.finalise_with_patches(patches)
                         })
                     }
                     AdbEntryKind::Global => {
-                        count_global += 1;
+                        counts.global += 1;
                         let (p, code) = dis::analyse_dummy(entry, res).unwrap();
                         pretty = p;
                         code
                     }
                     AdbEntryKind::Dummy => {
-                        count_dummy += 1;
+                        counts.dummy += 1;
                         let (p, code) = dis::analyse_dummy(entry, res).unwrap();
                         pretty = p;
                         code
                     }
                     AdbEntryKind::Scene => {
-                        count_scene += 1;
+                        counts.scene += 1;
                         let (p, code) = dis::analyse_dummy(entry, res).unwrap();
                         pretty = p;
                         code
                     }
                 };
+                let mut cfg_svg = None;
+                if let Some(graph) = &code.cfg {
+                    let svg = graph.svg();
+                    if !dryrun {
+                        let cfg = cfg.unwrap();
+                        std::fs::write(output.join(format!("{key}.{}", cfg.extension())), match cfg {
+                            GraphFormat::Dot => graph.dot(key),
+                            GraphFormat::Svg => svg.clone(),
+                        }).unwrap();
+                    }
+                    cfg_svg = Some(svg);
+                }
                 let hierarchy = root.get(key_parts[0]).flatten();
                 let rendered_hierarchy = hierarchy.render(key, &entries);
-                output.push(format!("{key}.html"));
                 let mut sorted_xrefs = entry.xrefs.clone();
                 sorted_xrefs.sort_by_cached_key(|xref| (xref.other_key.clone(), xref.loc));
-                if !dryrun {
-                    std::fs::write(&output, templates::Bytecode {
-                        title: key.to_string(),
-                        kind: &entry.kind,
-                        rendered_hierarchy: &rendered_hierarchy,
-                        rendered_breadcrumbs,
-                        code,
-                        pretty,
-                        xrefs: sorted_xrefs,
-                    }.render().unwrap()).unwrap();
+                let page = templates::Bytecode {
+                    title: key.to_string(),
+                    kind: &entry.kind,
+                    rendered_hierarchy: &rendered_hierarchy,
+                    rendered_breadcrumbs,
+                    code,
+                    pretty,
+                    cfg: cfg_svg,
+                    xrefs: sorted_xrefs,
+                };
+                let mut json = None;
+                if format == OutputFormat::Json {
+                    json = Some(templates::json::JsonObject::new(key, entry, page, region));
+                } else if !dryrun {
+                    std::fs::write(output.join(format!("{key}.{}", format.extension())), match format {
+                        OutputFormat::Html => page.render().unwrap(),
+                        OutputFormat::Text => page.render_text(false),
+                        OutputFormat::Markdown => page.render_text(true),
+                        OutputFormat::Json => unreachable!(),
+                    }).unwrap();
                 }
+                (key.as_str(), log, counts, json)
+            }, |(key, log, object_counts, json)| {
+                print!("{log}");
+                counts.add(object_counts);
+                if let Some(json) = json {
+                    json_objects.insert(key, json);
+                }
+            });
+            if format == OutputFormat::Json && !dryrun {
+                output.push("database.json");
+                let count = json_objects.len();
+                let file = std::io::BufWriter::new(std::fs::File::create(&output).unwrap());
+                serde_json::to_writer_pretty(file, &templates::json::JsonDatabase {
+                    objects: json_objects,
+                }).unwrap();
+                println!("{count} objects written to {output:?}");
                 output.pop();
             }
-            println!("code:    {count_code}, errored: {count_code_error}");
-            println!("globals: {count_global}");
-            println!("dummy:   {count_dummy}");
-            println!("raw:     {count_raw}");
-            println!("regions: {count_region}");
-            println!("strings: {count_string}");
-            println!("scenes:  {count_scene}");
+            println!("code:    {}, errored: {}, unstructured: {}, mistyped: {}", counts.code, counts.code_error, counts.unstructured.len(), counts.mistyped.len());
+            for key in &counts.unstructured {
+                println!("  - unstructured: {key}");
+            }
+            for key in &counts.mistyped {
+                println!("  - mistyped: {key}");
+            }
+            println!("globals: {}", counts.global);
+            println!("dummy:   {}", counts.dummy);
+            println!("raw:     {}", counts.raw);
+            println!("regions: {}", counts.region);
+            println!("strings: {}", counts.string);
+            println!("scenes:  {}", counts.scene);
         }
         CliCommand::Patch { output, .. } => {
             assert_ne!(db_path, output);
@@ -0,0 +1,1015 @@
+use once_cell::sync::Lazy;
+
+use crate::{asm::escape_string, dis::code::DisOp};
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum ScriptError {
+    UnexpectedCharacter(usize, char),
+    UnterminatedString(usize),
+    InvalidString(usize, String),
+    UnexpectedToken(usize, String),
+    UnexpectedEnd,
+    UnknownStatement(usize, String),
+    InvalidExpression(usize, String),
+    OutsideLoop(usize, String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Int(i64),
+    Str(String),
+    Word(String),
+    Sym(&'static str),
+}
+
+impl std::fmt::Display for Tok {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Str(s) => write!(f, "\"{}\"", escape_string(s)),
+            Self::Word(word) => write!(f, "{word}"),
+            Self::Sym(sym) => write!(f, "{sym}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    line: usize,
+    tok: Tok,
+}
+
+/// Symbols, longest first. `==s` and `+s` are the string comparison and
+/// concatenation operators of the decompiler.
+const SYMBOLS: &[&str] = &[
+    "<<=", ">>=", "==s",
+    "==", "!=", "<=", ">=", "<<", ">>", "&&", "||", "++", "--",
+    "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", ":=", "+s",
+    "+", "-", "*", "/", "%", "&", "|", "^", "~", "!", "<", ">", "=",
+    "(", ")", "[", "]", "{", "}", ",", ":", ".", ";",
+];
+
+fn parse_int(s: &str) -> Option<i64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => s.parse::<i64>().ok(),
+    }
+}
+
+fn unescape(line: usize, s: &str) -> Result<String, ScriptError> {
+    let mut ret = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => ret.push(match chars.next() {
+                Some('\\') => '\\',
+                Some('"') => '"',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                _ => return Err(ScriptError::InvalidString(line, s.to_string())),
+            }),
+            c => ret.push(c),
+        }
+    }
+    Ok(ret)
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ScriptError> {
+    let is_word_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut tokens = Vec::new();
+    for (line_idx, line) in source.lines().enumerate() {
+        let line_no = line_idx + 1;
+        let mut rest = line;
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() || rest.starts_with("//") {
+                break;
+            }
+            let c = rest.chars().next().unwrap();
+            let (tok, len) = if c == '"' {
+                let mut escaped = false;
+                let end = rest.char_indices()
+                    .skip(1)
+                    .find(|(_, c)| match c {
+                        _ if escaped => { escaped = false; false }
+                        '\\' => { escaped = true; false }
+                        '"' => true,
+                        _ => false,
+                    })
+                    .map(|(idx, _)| idx)
+                    .ok_or(ScriptError::UnterminatedString(line_no))?;
+                (Tok::Str(unescape(line_no, &rest[1..end])?), end + 1)
+            } else if is_word_char(c) || c == '$' {
+                let len = rest.char_indices()
+                    .skip(1)
+                    .find(|(_, c)| !is_word_char(*c))
+                    .map(|(idx, _)| idx)
+                    .unwrap_or(rest.len());
+                let word = &rest[..len];
+                match parse_int(word) {
+                    Some(value) if c.is_ascii_digit() => (Tok::Int(value), len),
+                    _ => (Tok::Word(word.to_string()), len),
+                }
+            } else {
+                let sym = SYMBOLS.iter()
+                    .find(|sym| rest.starts_with(**sym)
+                        && !(sym.ends_with('s') && rest[sym.len()..].starts_with(is_word_char)))
+                    .ok_or(ScriptError::UnexpectedCharacter(line_no, c))?;
+                (Tok::Sym(sym), sym.len())
+            };
+            tokens.push(Token { line: line_no, tok });
+            rest = &rest[len..];
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Int(i64),
+    Str(String),
+    Unop(&'static str, Box<Expr>),
+    Binop(&'static str, Box<Expr>, Box<Expr>),
+    Global(Box<Expr>),
+    ToStr(Box<Expr>),
+    ScreenName,
+    /// Opcode applied to the given arguments, pushed in order.
+    Op(DisOp, Vec<Expr>),
+}
+
+/// Binary operators, from the lowest precedence level to the highest. Each
+/// operator is listed with its opcode and whether its operands are printed
+/// by the decompiler in the order they are pushed.
+const BINOPS: &[&[(&str, Option<DisOp>, bool)]] = &[
+    &[("||", Some(DisOp::LogicOr), false)],
+    &[("&&", Some(DisOp::LogicAnd), false)],
+    &[("|", Some(DisOp::BitOr), false)],
+    &[("^", Some(DisOp::Xor), false)],
+    &[("&", Some(DisOp::BitAnd), false)],
+    &[("==", Some(DisOp::Eq), false), ("!=", Some(DisOp::Ne), false), ("==s", None, false)],
+    &[("<", Some(DisOp::Lt), false), (">", Some(DisOp::Gt), false), ("<=", Some(DisOp::Le), false), (">=", Some(DisOp::Ge), false)],
+    &[("<<", Some(DisOp::Shl), true), (">>", Some(DisOp::Shr), true)],
+    &[("+", Some(DisOp::Add), false), ("-", Some(DisOp::Sub), true), ("+s", None, true)],
+    &[("*", Some(DisOp::Mul), false), ("/", Some(DisOp::Div), true), ("%", Some(DisOp::Mod), true)],
+];
+
+/// Statements which map onto a single opcode. Placeholders `$a`, `$b`, `$c`
+/// are expressions, pushed in the order `$c`, `$b`, `$a` (i.e., `$a` ends up
+/// on top of the stack). The forms are the ones printed by the decompiler.
+const STATEMENTS: &[(&str, DisOp)] = &[
+    ("global[$a] = $b", DisOp::GlbSetPop),
+    ("global[$a] += $b", DisOp::GlbAdd),
+    ("global[$a] -= $b", DisOp::GlbSub),
+    ("global[$a] *= $b", DisOp::GlbMul),
+    ("global[$a] /= $b", DisOp::GlbDiv),
+    ("global[$a] %= $b", DisOp::GlbMod),
+    ("global[$a] <<= $b", DisOp::GlbShl),
+    ("global[$a] >>= $b", DisOp::GlbShr),
+    ("global[$a] &= $b", DisOp::GlbBitAnd),
+    ("global[$a] |= $b", DisOp::GlbBirOr),
+    ("global[$a] ^= $b", DisOp::GlbBitXor),
+    ("++global[$a]", DisOp::GlbPreInc),
+    ("--global[$a]", DisOp::GlbPreDec),
+    ("global[$a]++", DisOp::GlbPostInc),
+    ("global[$a]--", DisOp::GlbPostDec),
+    ("self.cursor = $a", DisOp::SetCursor),
+    ("self.region = $a", DisOp::SetRegion),
+    ("self.picture = $a", DisOp::SetPicture),
+    ("self.animation = $a", DisOp::SetAnim),
+    ("self.priority = $a", DisOp::SetPriority),
+    ("self.displayName = $a", DisOp::SetDisplay),
+    ("self.walkmap = $a", DisOp::SetWalkmap),
+    ("obj.add($a)", DisOp::AddObject),
+    ("obj[$b].displayName = $a", DisOp::SetChoiceText),
+    ("obj[$b].walkmap = $a", DisOp::SetObjWalkmap),
+    ("obj[$c].pos = ($b, $a)", DisOp::SetPos),
+    ("clone.add($b, $a)", DisOp::CloneCreate),
+    ("screen.remove($a)", DisOp::ScrRemove),
+    ("screen.show(4F, $a)", DisOp::SwitchTo4F),
+    ("screen.show(50, $a)", DisOp::SwitchTo50),
+    ("screen.back()", DisOp::ScrPrev),
+    ("char[$c].associateObj(id: $b, obj: $a)", DisOp::ChrAssocObj),
+    ("char[$a].unload()", DisOp::ChrUnload),
+    ("char[$b].animate($a)", DisOp::ChrAnimate),
+    ("char[$a].hide()", DisOp::ChrHide),
+    ("char[$a].show()", DisOp::ChrShow),
+    ("char[$a].disable()", DisOp::ChrDisable),
+    ("char[$a].enable()", DisOp::ChrEnable),
+    ("char[$c].moveTo(pos: $b, pose: $a, usermove)", DisOp::ChrMoveUser),
+    ("char[$c].moveTo(pos: $b, pose: $a, non-usermove)", DisOp::ChrMove),
+    ("char[$b].leave(pos: $a)", DisOp::ChrLeave),
+    ("char[$b].pointTo($a)", DisOp::ChrPoint),
+    ("char[$b].stop($a)", DisOp::ChrStop),
+    ("userInput.disable()", DisOp::UserDisable),
+    ("userInput.enable()", DisOp::UserEnable),
+    ("cursors.set($a)", DisOp::CursorSet),
+    ("cursors.add($a)", DisOp::CursorAdd),
+    ("cursors.remove($a)", DisOp::CursorRemove),
+    ("inv.add($a)", DisOp::InvAdd6F),
+    ("inv.remove($a)", DisOp::InvRemove),
+    ("inv.enable()", DisOp::InvEnable),
+    ("cd.play($a)", DisOp::CdPlay),
+    ("cd.stop()", DisOp::CdStop),
+    ("cd.pause()", DisOp::CdPause),
+    ("cd.resume()", DisOp::CdResume),
+    ("anim.play($a)", DisOp::AnimPlay),
+    ("anim.pos = ($b, $a)", DisOp::AnimPos),
+    ("sample.play($a)", DisOp::SmpPlay),
+    ("sample.loop = true", DisOp::SmpLoop),
+    ("delay($a)", DisOp::Delay),
+    ("fonts.size = (w: $b, h: $a)", DisOp::FntSetSize),
+    ("fonts[$b] = $a", DisOp::FntCreate),
+    ("$c.picture = fonts[$a].render($b)", DisOp::SetTextPicture),
+    ("films.start(video: $a, audio: $b)", DisOp::FlmStart),
+    ("films.stop()", DisOp::FlmStop),
+    ("films.subtitles = $a", DisOp::FlmSub),
+];
+
+static STATEMENT_TOKENS: Lazy<Vec<(Vec<Tok>, DisOp)>> = Lazy::new(|| STATEMENTS
+    .iter()
+    .map(|(pattern, op)| (tokenize(pattern).unwrap().into_iter().map(|t| t.tok).collect(), *op))
+    .collect());
+
+fn find_op(name: &str) -> Option<DisOp> {
+    (0..256)
+        .find(|idx| !DisOp::NAME[*idx].is_empty() && DisOp::NAME[*idx].eq_ignore_ascii_case(name))
+        .and_then(|idx| DisOp::VARIANTS[idx])
+}
+
+enum Header {
+    If(Expr),
+    OnInit,
+    OnInteract(bool),
+    OnCombine(Expr),
+    OnKey(Expr),
+}
+
+struct Compiler {
+    tokens: Vec<Token>,
+    pos: usize,
+    output: Vec<String>,
+    strings: Vec<String>,
+    label_counter: usize,
+    /// Continue and break labels of the enclosing loops.
+    loops: Vec<(String, String)>,
+}
+
+impl Compiler {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos).map(|t| &t.tok)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Tok> {
+        self.tokens.get(self.pos + offset).map(|t| &t.tok)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos)
+            .or(self.tokens.last())
+            .map(|t| t.line)
+            .unwrap_or(0)
+    }
+
+    fn next(&mut self) -> Result<Tok, ScriptError> {
+        let tok = self.tokens.get(self.pos)
+            .ok_or(ScriptError::UnexpectedEnd)?
+            .tok
+            .clone();
+        self.pos += 1;
+        Ok(tok)
+    }
+
+    fn is_sym(&self, sym: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Sym(s)) if *s == sym)
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Word(w)) if w == word)
+    }
+
+    fn eat_sym(&mut self, sym: &str) -> bool {
+        let found = self.is_sym(sym);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        let found = self.is_word(word);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_sym(&mut self, sym: &str) -> Result<(), ScriptError> {
+        if self.eat_sym(sym) {
+            return Ok(());
+        }
+        Err(self.unexpected())
+    }
+
+    fn expect_word(&mut self) -> Result<String, ScriptError> {
+        match self.peek() {
+            Some(Tok::Word(word)) => {
+                let word = word.clone();
+                self.pos += 1;
+                Ok(word)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn unexpected(&self) -> ScriptError {
+        match self.peek() {
+            Some(tok) => ScriptError::UnexpectedToken(self.line(), tok.to_string()),
+            None => ScriptError::UnexpectedEnd,
+        }
+    }
+
+    /// Whether the current statement ends here: statements are separated by
+    /// line breaks or semicolons, and blocks end with `}`.
+    fn at_statement_end(&self) -> bool {
+        match self.tokens.get(self.pos) {
+            None => true,
+            // `)` ends the update statement of a `for` loop.
+            Some(Token { tok: Tok::Sym(";" | "}" | ")"), .. }) => true,
+            Some(Token { line, .. }) => *line != self.tokens[self.pos - 1].line,
+        }
+    }
+
+    fn end_statement(&mut self) -> Result<(), ScriptError> {
+        if !self.at_statement_end() {
+            return Err(self.unexpected());
+        }
+        self.eat_sym(";");
+        Ok(())
+    }
+
+    fn new_label(&mut self) -> String {
+        self.label_counter += 1;
+        format!("L{}", self.label_counter)
+    }
+
+    fn emit(&mut self, op: DisOp, operand: Option<String>) {
+        match operand {
+            Some(operand) => self.output.push(format!("    {} {operand}", DisOp::NAME[op as usize])),
+            None => self.output.push(format!("    {}", DisOp::NAME[op as usize])),
+        }
+    }
+
+    fn emit_label(&mut self, label: &str) {
+        self.output.push(format!("{label}:"));
+    }
+
+    fn intern(&mut self, s: &str) -> usize {
+        match self.strings.iter().position(|other| other == s) {
+            Some(idx) => idx,
+            None => {
+                self.strings.push(s.to_string());
+                self.strings.len() - 1
+            }
+        }
+    }
+
+    fn emit_push(&mut self, value: u32, operand: String) {
+        match value {
+            0..=0xFF => self.emit(DisOp::PushImm8a, Some(operand)),
+            0x100..=0xFFFF => self.emit(DisOp::PushImm16a, Some(operand)),
+            _ => self.emit(DisOp::PushImm32, Some(operand)),
+        }
+    }
+
+    // Expressions
+
+    fn parse_expr(&mut self) -> Result<Expr, ScriptError> {
+        self.parse_binop(0)
+    }
+
+    fn parse_binop(&mut self, level: usize) -> Result<Expr, ScriptError> {
+        if level == BINOPS.len() {
+            return self.parse_unary();
+        }
+        let mut lhs = self.parse_binop(level + 1)?;
+        while let Some(Tok::Sym(sym)) = self.peek()
+            && let Some((name, _, _)) = BINOPS[level].iter().find(|(name, _, _)| name == sym) {
+            self.pos += 1;
+            let rhs = self.parse_binop(level + 1)?;
+            lhs = Expr::Binop(name, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ScriptError> {
+        for op in ["-", "~", "!"] {
+            if self.eat_sym(op) {
+                return Ok(Expr::Unop(op, Box::new(self.parse_unary()?)));
+            }
+        }
+        self.parse_primary()
+    }
+
+    /// Parses `[expr]`.
+    fn parse_index(&mut self) -> Result<Expr, ScriptError> {
+        self.expect_sym("[")?;
+        let index = self.parse_expr()?;
+        self.expect_sym("]")?;
+        Ok(index)
+    }
+
+    /// Parses `(expr, ...)`.
+    fn parse_args(&mut self) -> Result<Vec<Expr>, ScriptError> {
+        self.expect_sym("(")?;
+        let mut args = Vec::new();
+        while !self.eat_sym(")") {
+            if !args.is_empty() {
+                self.expect_sym(",")?;
+            }
+            args.push(self.parse_expr()?);
+        }
+        Ok(args)
+    }
+
+    fn parse_arg(&mut self) -> Result<Expr, ScriptError> {
+        let mut args = self.parse_args()?;
+        if args.len() != 1 {
+            return Err(ScriptError::InvalidExpression(self.line(), format!("expected 1 argument, found {}", args.len())));
+        }
+        Ok(args.pop().unwrap())
+    }
+
+    fn parse_field(&mut self, fields: &[(&str, DisOp)]) -> Result<DisOp, ScriptError> {
+        self.expect_sym(".")?;
+        let field = self.expect_word()?;
+        fields.iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, op)| *op)
+            .ok_or_else(|| ScriptError::InvalidExpression(self.line(), field))
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ScriptError> {
+        let line = self.line();
+        let word = match self.next()? {
+            Tok::Int(value) => return Ok(Expr::Int(value)),
+            Tok::Str(s) => return Ok(Expr::Str(s)),
+            Tok::Sym("(") => {
+                let expr = self.parse_expr()?;
+                self.expect_sym(")")?;
+                return Ok(expr);
+            }
+            Tok::Word(word) => word,
+            tok => return Err(ScriptError::UnexpectedToken(line, tok.to_string())),
+        };
+        Ok(match word.as_str() {
+            "global" => Expr::Global(Box::new(self.parse_index()?)),
+            "mouse" => Expr::Op(self.parse_field(&[("x", DisOp::GetMouseX), ("y", DisOp::GetMouseY)])?, vec![]),
+            "self" => Expr::Op(self.parse_field(&[("name", DisOp::CloneSelf)])?, vec![]),
+            "screen" => {
+                self.expect_sym(".")?;
+                if self.expect_word()? != "name" {
+                    return Err(ScriptError::InvalidExpression(line, word));
+                }
+                Expr::ScreenName
+            }
+            "region" => {
+                let index = self.parse_index()?;
+                Expr::Op(self.parse_field(&[("x", DisOp::GetRegX), ("y", DisOp::GetRegY)])?, vec![index])
+            }
+            "obj" => {
+                let index = self.parse_index()?;
+                let op = self.parse_field(&[
+                    ("x", DisOp::GetObjX),
+                    ("y", DisOp::GetObjY),
+                    ("w", DisOp::GetObjW),
+                    ("h", DisOp::GetObjH),
+                ])?;
+                Expr::Op(op, vec![index])
+            }
+            "char" => {
+                let op = self.parse_field(&[("phase", DisOp::GetCharPhase), ("x", DisOp::GetCharX), ("y", DisOp::GetCharY)])?;
+                Expr::Op(op, vec![self.parse_arg()?])
+            }
+            "inv" => {
+                let op = self.parse_field(&[("has", DisOp::InvHasBD)])?;
+                Expr::Op(op, vec![self.parse_arg()?])
+            }
+            "vars" => Expr::Op(DisOp::GetVarInt, vec![self.parse_index()?]),
+            "random" => Expr::Op(DisOp::Random, vec![self.parse_arg()?]),
+            "str" => Expr::ToStr(Box::new(self.parse_arg()?)),
+            "isset" => match self.parse_arg()? {
+                Expr::Global(name) => Expr::Op(DisOp::GlobalIsset, vec![*name]),
+                _ => return Err(ScriptError::InvalidExpression(line, "isset requires a global".to_string())),
+            },
+            _ => {
+                // Any opcode which pushes a value can be used directly.
+                let op = find_op(&word)
+                    .filter(|op| DisOp::IMM_SIZE[*op as usize] == 0 && DisOp::STACK_OUT[*op as usize] == 1)
+                    .ok_or_else(|| ScriptError::InvalidExpression(line, word.clone()))?;
+                let args = self.parse_args()?;
+                if args.len() != DisOp::STACK_IN[op as usize] {
+                    return Err(ScriptError::InvalidExpression(line, format!("{word} takes {} arguments", DisOp::STACK_IN[op as usize])));
+                }
+                Expr::Op(op, args)
+            }
+        })
+    }
+
+    fn compile_expr(&mut self, line: usize, expr: &Expr) -> Result<(), ScriptError> {
+        match expr {
+            Expr::Int(value) => {
+                let value = u32::try_from(*value)
+                    .map_err(|_| ScriptError::InvalidExpression(line, format!("{value} out of range")))?;
+                self.emit_push(value, format!("0x{value:x}"));
+            }
+            Expr::Str(s) => {
+                let idx = self.intern(s);
+                self.emit_push(idx as u32, format!("\"{}\"", escape_string(s)));
+            }
+            Expr::Unop(op, val) => {
+                self.compile_expr(line, val)?;
+                self.emit(match *op {
+                    "-" => DisOp::Neg,
+                    "~" => DisOp::BitNot,
+                    _ => DisOp::LogicNot,
+                }, None);
+            }
+            Expr::Binop("==s", box Expr::ScreenName, box other)
+                | Expr::Binop("==s", box other, box Expr::ScreenName) => {
+                self.compile_expr(line, other)?;
+                self.emit(DisOp::ScrIs, None);
+            }
+            Expr::Binop("+s", lhs, box Expr::ToStr(rhs)) => {
+                self.compile_expr(line, lhs)?;
+                self.compile_expr(line, rhs)?;
+                self.emit(DisOp::CloneName, None);
+            }
+            Expr::Binop(name, lhs, rhs) => {
+                let Some((_, Some(op), in_order)) = BINOPS.iter()
+                    .flat_map(|level| level.iter())
+                    .find(|(other, _, _)| other == name) else {
+                    return Err(ScriptError::InvalidExpression(line, format!("unsupported use of {name}")));
+                };
+                if *in_order {
+                    self.compile_expr(line, lhs)?;
+                    self.compile_expr(line, rhs)?;
+                } else {
+                    self.compile_expr(line, rhs)?;
+                    self.compile_expr(line, lhs)?;
+                }
+                self.emit(*op, None);
+            }
+            Expr::Global(box Expr::Str(name)) => {
+                let idx = self.intern(name);
+                if idx > 0xFF {
+                    return Err(ScriptError::InvalidExpression(line, format!("global name {name:?} does not fit in the string pool")));
+                }
+                self.emit(DisOp::GlbGet, Some(format!("\"{}\"", escape_string(name))));
+            }
+            Expr::Global(box Expr::Binop("+s", lhs, rhs)) => {
+                self.compile_expr(line, lhs)?;
+                self.compile_expr(line, rhs)?;
+                self.emit(DisOp::CloneGetVar, None);
+            }
+            Expr::Global(..) => return Err(ScriptError::InvalidExpression(line, "global names must be constant".to_string())),
+            Expr::ToStr(..) => return Err(ScriptError::InvalidExpression(line, "str(..) can only be appended to a string".to_string())),
+            Expr::ScreenName => return Err(ScriptError::InvalidExpression(line, "screen.name can only be compared".to_string())),
+            Expr::Op(op, args) => {
+                for arg in args {
+                    self.compile_expr(line, arg)?;
+                }
+                self.emit(*op, None);
+            }
+        }
+        Ok(())
+    }
+
+    // Statements
+
+    fn compile_block(&mut self) -> Result<(), ScriptError> {
+        self.expect_sym("{")?;
+        while !self.eat_sym("}") {
+            self.compile_statement()?;
+        }
+        Ok(())
+    }
+
+    fn parse_header(&mut self) -> Result<Option<Header>, ScriptError> {
+        if self.eat_word("if") {
+            return Ok(Some(Header::If(self.parse_expr()?)));
+        }
+        if !self.is_word("on") {
+            return Ok(None);
+        }
+        self.pos += 1;
+        let line = self.line();
+        Ok(Some(match self.expect_word()?.as_str() {
+            "init" => Header::OnInit,
+            "interact" => {
+                self.expect_sym("(")?;
+                let button = self.expect_word()?;
+                self.expect_sym(")")?;
+                match button.as_str() {
+                    "LMB" => Header::OnInteract(false),
+                    "RMB" => Header::OnInteract(true),
+                    _ => return Err(ScriptError::UnexpectedToken(line, button)),
+                }
+            }
+            "combine" => Header::OnCombine(self.parse_arg()?),
+            "key" => Header::OnKey(self.parse_arg()?),
+            other => return Err(ScriptError::UnexpectedToken(line, other.to_string())),
+        }))
+    }
+
+    /// Compiles an `if` or `on` statement, with its `else` branches. The body
+    /// follows the test (or the handler registration), which jumps over it.
+    fn compile_chain(&mut self, header: Header) -> Result<(), ScriptError> {
+        let line = self.line();
+        let else_label = self.new_label();
+        match header {
+            Header::If(test) => {
+                self.compile_expr(line, &test)?;
+                self.emit(DisOp::Jez, Some(else_label.clone()));
+            }
+            Header::OnInit => self.emit(DisOp::OnInit, Some(else_label.clone())),
+            Header::OnInteract(false) => self.emit(DisOp::OnInteractL, Some(else_label.clone())),
+            Header::OnInteract(true) => self.emit(DisOp::OnInteractR, Some(else_label.clone())),
+            Header::OnCombine(with) => {
+                self.compile_expr(line, &with)?;
+                self.emit(DisOp::OnCombine, Some(else_label.clone()));
+            }
+            Header::OnKey(key) => {
+                self.compile_expr(line, &key)?;
+                self.emit(DisOp::OnKey, Some(else_label.clone()));
+            }
+        }
+        self.compile_block()?;
+        if !self.eat_word("else") {
+            self.emit_label(&else_label);
+            return Ok(());
+        }
+        let end_label = self.new_label();
+        self.emit(DisOp::Jmp, Some(end_label.clone()));
+        self.emit_label(&else_label);
+        // The decompiler labels else branches with the kind of the edge, e.g.
+        // `else conditional fallthrough { .. }`.
+        let start = self.pos;
+        while let Some(Tok::Word(word)) = self.peek()
+            && ["conditional", "on", "init", "interact", "key", "combine", "unknown"].contains(&word.as_str()) {
+            self.pos += 1;
+        }
+        if !self.eat_word("fallthrough") {
+            self.pos = start;
+        }
+        match self.parse_header()? {
+            Some(header) => self.compile_chain(header)?,
+            None => self.compile_block()?,
+        }
+        self.emit_label(&end_label);
+        Ok(())
+    }
+
+    /// Tries to match one of `STATEMENTS` at the current position.
+    fn match_statement(&mut self) -> Result<bool, ScriptError> {
+        let line = self.line();
+        let start = self.pos;
+        'patterns: for (pattern, op) in STATEMENT_TOKENS.iter() {
+            self.pos = start;
+            let mut args: [Option<Expr>; 3] = Default::default();
+            for tok in pattern {
+                match tok {
+                    Tok::Word(placeholder) if placeholder.starts_with('$') => {
+                        let Ok(expr) = self.parse_expr() else { continue 'patterns; };
+                        args[match placeholder.as_str() { "$a" => 0, "$b" => 1, _ => 2 }] = Some(expr);
+                    }
+                    _ => if self.peek() != Some(tok) {
+                        continue 'patterns;
+                    } else {
+                        self.pos += 1;
+                    },
+                }
+            }
+            if !self.at_statement_end() {
+                continue;
+            }
+            // `global[c +s b] = a` sets a clone variable, with the operands
+            // of the name pushed separately.
+            let mut op = *op;
+            if matches!(op, DisOp::GlbSetPop) && let [Some(Expr::Binop("+s", c, b)), Some(a), None] = &args {
+                args = [Some(a.clone()), Some(*b.clone()), Some(*c.clone())];
+                op = DisOp::CloneSetVar;
+            }
+            for arg in args.iter().rev().flatten() {
+                self.compile_expr(line, arg)?;
+            }
+            self.emit(op, None);
+            // Values pushed by statements are discarded.
+            for _ in 0..DisOp::STACK_OUT[op as usize] {
+                self.emit(DisOp::Pop, None);
+            }
+            return Ok(true);
+        }
+        self.pos = start;
+        Ok(false)
+    }
+
+    fn compile_statement(&mut self) -> Result<(), ScriptError> {
+        let line = self.line();
+        if let Some(header) = self.parse_header()? {
+            return self.compile_chain(header);
+        }
+        if self.eat_word("loop") {
+            let continue_label = self.new_label();
+            let break_label = self.new_label();
+            self.emit_label(&continue_label);
+            self.loops.push((continue_label.clone(), break_label.clone()));
+            self.compile_block()?;
+            self.loops.pop();
+            self.emit(DisOp::Jmp, Some(continue_label));
+            self.emit_label(&break_label);
+            return Ok(());
+        }
+        if self.eat_word("while") {
+            let continue_label = self.new_label();
+            let break_label = self.new_label();
+            self.emit_label(&continue_label);
+            let test = self.parse_expr()?;
+            self.compile_expr(line, &test)?;
+            self.emit(DisOp::Jez, Some(break_label.clone()));
+            self.loops.push((continue_label.clone(), break_label.clone()));
+            self.compile_block()?;
+            self.loops.pop();
+            self.emit(DisOp::Jmp, Some(continue_label));
+            self.emit_label(&break_label);
+            return Ok(());
+        }
+        if self.eat_word("do") {
+            let start_label = self.new_label();
+            let continue_label = self.new_label();
+            let break_label = self.new_label();
+            self.emit_label(&start_label);
+            self.loops.push((continue_label.clone(), break_label.clone()));
+            self.compile_block()?;
+            self.loops.pop();
+            self.emit_label(&continue_label);
+            if !self.eat_word("while") {
+                return Err(self.unexpected());
+            }
+            let line = self.line();
+            let test = self.parse_expr()?;
+            self.compile_expr(line, &test)?;
+            self.emit(DisOp::Jez, Some(break_label.clone()));
+            self.emit(DisOp::Jmp, Some(start_label));
+            self.emit_label(&break_label);
+            return self.end_statement();
+        }
+        if self.eat_word("for") {
+            self.expect_sym("(")?;
+            self.compile_statement()?;
+            let continue_label = self.new_label();
+            let break_label = self.new_label();
+            let start_label = self.new_label();
+            self.emit_label(&start_label);
+            let test = self.parse_expr()?;
+            self.compile_expr(line, &test)?;
+            self.emit(DisOp::Jez, Some(break_label.clone()));
+            self.expect_sym(";")?;
+            // The update is compiled after the body.
+            let output = std::mem::take(&mut self.output);
+            self.compile_statement()?;
+            let update = std::mem::replace(&mut self.output, output);
+            self.expect_sym(")")?;
+            self.loops.push((continue_label.clone(), break_label.clone()));
+            self.compile_block()?;
+            self.loops.pop();
+            self.emit_label(&continue_label);
+            self.output.extend(update);
+            self.emit(DisOp::Jmp, Some(start_label));
+            self.emit_label(&break_label);
+            return Ok(());
+        }
+        if self.is_word("wait") && matches!(self.peek_at(1), Some(Tok::Word(w)) if w == "while") {
+            self.pos += 2;
+            let continue_label = self.new_label();
+            let break_label = self.new_label();
+            self.emit_label(&continue_label);
+            let test = self.parse_expr()?;
+            self.compile_expr(line, &test)?;
+            self.emit(DisOp::Jez, Some(break_label.clone()));
+            self.emit(DisOp::Tick, None);
+            self.emit(DisOp::Jmp, Some(continue_label));
+            self.emit_label(&break_label);
+            return self.end_statement();
+        }
+        if self.eat_word("switch") {
+            // The test is evaluated once, and kept on the stack while the
+            // cases are compared to it. It is popped before the body of the
+            // matching case, or after the last case if none matches.
+            let test = self.parse_expr()?;
+            self.compile_expr(line, &test)?;
+            let end_label = self.new_label();
+            self.expect_sym("{")?;
+            while !self.eat_sym("}") {
+                if !self.eat_word("case") {
+                    return Err(self.unexpected());
+                }
+                let line = self.line();
+                let value = self.parse_expr()?;
+                self.expect_sym(":")?;
+                let next_label = self.new_label();
+                self.emit(DisOp::Dup, None);
+                self.compile_expr(line, &value)?;
+                self.emit(DisOp::Eq, None);
+                self.emit(DisOp::Jez, Some(next_label.clone()));
+                self.emit(DisOp::Pop, None);
+                while !self.is_word("case") && !self.is_sym("}") {
+                    self.compile_statement()?;
+                }
+                self.emit(DisOp::Jmp, Some(end_label.clone()));
+                self.emit_label(&next_label);
+            }
+            self.emit(DisOp::Pop, None);
+            self.emit_label(&end_label);
+            return Ok(());
+        }
+        for (word, op) in [("exit", DisOp::Exit), ("tick", DisOp::Tick), ("quit", DisOp::Quit)] {
+            if self.eat_word(word) {
+                if self.at_statement_end() {
+                    self.emit(op, None);
+                    return self.end_statement();
+                }
+                self.pos -= 1;
+            }
+        }
+        for (idx, word) in ["continue", "break"].into_iter().enumerate() {
+            if self.eat_word(word) {
+                let Some(labels) = self.loops.last() else {
+                    return Err(ScriptError::OutsideLoop(line, word.to_string()));
+                };
+                let label = if idx == 0 { labels.0.clone() } else { labels.1.clone() };
+                self.emit(DisOp::Jmp, Some(label));
+                return self.end_statement();
+            }
+        }
+        if self.is_word("self") && matches!(self.peek_at(2), Some(Tok::Word(w)) if w == "cursor")
+            && matches!(self.peek_at(4), Some(Tok::Word(w)) if w == "default") {
+            self.pos += 5;
+            self.emit_push(255, "0xff".to_string());
+            self.emit(DisOp::SetCursor, None);
+            return self.end_statement();
+        }
+        if self.eat_word("var") {
+            let name = self.parse_index()?;
+            self.expect_sym(":=")?;
+            let value = self.parse_expr()?;
+            self.compile_expr(line, &name)?;
+            self.compile_expr(line, &value)?;
+            self.emit(if matches!(value, Expr::Str(_)) { DisOp::SetVarString } else { DisOp::SetVarInt }, None);
+            return self.end_statement();
+        }
+        if self.match_statement()? {
+            return self.end_statement();
+        }
+        // Any other opcode can be used as a function call, with its arguments
+        // pushed in order.
+        if let Some(Tok::Word(word)) = self.peek()
+            && let Some(op) = find_op(word)
+            && DisOp::IMM_SIZE[op as usize] == 0
+            && !op.is_jump() {
+            let word = word.clone();
+            self.pos += 1;
+            let args = self.parse_args()?;
+            if args.len() != DisOp::STACK_IN[op as usize] {
+                return Err(ScriptError::InvalidExpression(line, format!("{word} takes {} arguments", DisOp::STACK_IN[op as usize])));
+            }
+            self.compile_expr(line, &Expr::Op(op, args))?;
+            for _ in 0..DisOp::STACK_OUT[op as usize] {
+                self.emit(DisOp::Pop, None);
+            }
+            return self.end_statement();
+        }
+        let text = self.tokens[self.pos..].iter()
+            .take_while(|t| t.line == line)
+            .map(|t| t.tok.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        Err(ScriptError::UnknownStatement(line, text))
+    }
+}
+
+/// Compiles a script, in the dialect printed by the decompiler, into assembly
+/// source accepted by `asm::assemble`. The script is the plain text of the
+/// decompiled output, without addresses and annotations. Supported are:
+///
+/// - `if (..) { .. } else { .. }`, also with `else if`, and with the edge
+///   labels of the decompiler (`else conditional fallthrough { .. }`);
+/// - `on init`, `on interact (LMB)`, `on interact (RMB)`, `on combine (..)`,
+///   and `on key (..)` handlers, with the same `else` forms;
+/// - `loop`, `while (..)`, `do { .. } while (..)`, `for (..; ..; ..)`,
+///   `wait while (..)`, `break`, `continue`;
+/// - `switch (..) { case ..: .. }`;
+/// - `exit`, `tick`, `quit`;
+/// - the statements and expressions printed by the decompiler for opcodes
+///   with a known meaning, e.g. `global["x"] = 1`, `char["x"].moveTo(..)`,
+///   `inv.has("x")`, `a ==s screen.name`, `global["x" +s str(1)] = 2`;
+/// - any other opcode without an immediate, by its mnemonic, e.g.
+///   `UnkC8(1, 2)`, with the arguments pushed in the given order.
+///
+/// Statements are separated by line breaks or semicolons. Comments start with
+/// `//`. Values pushed by statements (e.g. by `global["x"] += 1`) are popped.
+pub fn compile(source: &str) -> Result<String, ScriptError> {
+    let mut compiler = Compiler {
+        tokens: tokenize(source)?,
+        pos: 0,
+        output: Vec::new(),
+        strings: Vec::new(),
+        label_counter: 0,
+        loops: Vec::new(),
+    };
+    while compiler.pos < compiler.tokens.len() {
+        compiler.compile_statement()?;
+    }
+    let mut output = compiler.output.join("\n");
+    output.push('\n');
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{asm, dis::code::opmap::OpcodeMap};
+
+    use super::*;
+
+    /// Compiles a script, checks that the result assembles, and returns the
+    /// assembly with labels and indentation stripped.
+    fn compiled(source: &str) -> Vec<String> {
+        let compiled = compile(source).unwrap();
+        asm::assemble(&compiled, &OpcodeMap::identity()).unwrap();
+        compiled.lines().map(|line| line.trim().to_string()).filter(|line| !line.is_empty()).collect()
+    }
+
+    #[test]
+    fn if_else() {
+        assert_eq!(compiled("if (global[\"x\"] == 1) {\n  global[\"a\"] = 1\n} else {\n  global[\"a\"] = 2\n}"), [
+            "PushImm8a 0x1", "GlbGet \"x\"", "Eq", "Jez L1",
+            "PushImm8a 0x1", "PushImm8a \"a\"", "GlbSetPop", "Jmp L2",
+            "L1:", "PushImm8a 0x2", "PushImm8a \"a\"", "GlbSetPop",
+            "L2:",
+        ]);
+    }
+
+    #[test]
+    fn while_break_continue() {
+        assert_eq!(compiled("while (global[\"x\"] < 3) {\n  if (global[\"y\"]) {\n    break\n  }\n  continue\n}"), [
+            "L1:", "PushImm8a 0x3", "GlbGet \"x\"", "Lt", "Jez L2",
+            "GlbGet \"y\"", "Jez L3", "Jmp L2",
+            "L3:", "Jmp L1", "Jmp L1",
+            "L2:",
+        ]);
+    }
+
+    #[test]
+    fn do_while() {
+        assert_eq!(compiled("do {\n  global[\"x\"] += 1\n} while (global[\"x\"] < 3)"), [
+            "L1:", "PushImm8a 0x1", "PushImm8a \"x\"", "GlbAdd", "Pop",
+            "L2:", "PushImm8a 0x3", "GlbGet \"x\"", "Lt", "Jez L3", "Jmp L1",
+            "L3:",
+        ]);
+    }
+
+    #[test]
+    fn for_loop() {
+        assert_eq!(compiled("for (global[\"i\"] = 0; global[\"i\"] < 3; global[\"i\"] += 1) {\n  tick\n}"), [
+            "PushImm8a 0x0", "PushImm8a \"i\"", "GlbSetPop",
+            "L3:", "PushImm8a 0x3", "GlbGet \"i\"", "Lt", "Jez L2", "Tick",
+            "L1:", "PushImm8a 0x1", "PushImm8a \"i\"", "GlbAdd", "Pop", "Jmp L3",
+            "L2:",
+        ]);
+    }
+
+    /// The test of a `switch` is evaluated once, and popped on every path.
+    #[test]
+    fn switch_evaluates_test_once() {
+        assert_eq!(compiled("switch (global[\"x\"]) {\ncase 1:\n  global[\"a\"] = 1\ncase 2:\n  exit\n}"), [
+            "GlbGet \"x\"",
+            "Dup", "PushImm8a 0x1", "Eq", "Jez L2", "Pop",
+            "PushImm8a 0x1", "PushImm8a \"a\"", "GlbSetPop", "Jmp L1",
+            "L2:", "Dup", "PushImm8a 0x2", "Eq", "Jez L3", "Pop",
+            "Exit", "Jmp L1",
+            "L3:", "Pop",
+            "L1:",
+        ]);
+    }
+
+    #[test]
+    fn handler() {
+        assert_eq!(compiled("on interact (LMB) {\n  exit\n}"), ["OnInteractL L1", "Exit", "L1:"]);
+    }
+
+    #[test]
+    fn break_outside_loop() {
+        assert!(matches!(compile("break"), Err(ScriptError::OutsideLoop(1, _))));
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{adb::{AdbEntry, AdbXref}, dis::{DisHandler, DisLine, DisRegion}};
+
+use super::{text::strip_markup, Bytecode};
+
+/// One object of the analysed database, as written by `--format json`. The
+/// script and the handlers are rendered as plain text from the IR, the lines
+/// of the listing are stripped of markup, the same way as for the text output.
+#[derive(Serialize)]
+pub struct JsonObject<'a> {
+    pub kind: &'static str,
+    pub name: Option<&'a str>,
+    pub size: usize,
+    pub xrefs: Vec<AdbXref>,
+    pub script: Option<String>,
+    pub handlers: Vec<DisHandler>,
+    pub lines: Vec<DisLine>,
+    pub region: Option<DisRegion>,
+    pub globals: Option<BTreeMap<u32, &'a str>>,
+}
+
+impl<'a> JsonObject<'a> {
+    pub fn new(key: &str, entry: &'a AdbEntry, page: Bytecode, region: Option<DisRegion>) -> Self {
+        Self {
+            kind: entry.describe(key),
+            name: entry.name.as_deref(),
+            size: entry.size(),
+            xrefs: page.xrefs,
+            // Regions are "decompiled" into an SVG, which is covered by
+            // `region` instead.
+            script: page.pretty
+                .filter(|_| region.is_none())
+                .map(|pretty| pretty.text),
+            handlers: page.code.handlers,
+            lines: page.code.lines.into_iter()
+                .map(|line| DisLine {
+                    span: line.span,
+                    hex: strip_markup(&line.hex),
+                    asm: line.asm.map(|s| strip_markup(&s)),
+                    decomp: line.decomp.map(|s| strip_markup(&s)),
+                    comments: line.comments.map(|s| strip_markup(&s)),
+                })
+                .collect(),
+            region,
+            globals: entry.global.as_ref()
+                .map(|global| global.values.iter().map(|(k, v)| (*k, v.as_str())).collect()),
+        }
+    }
+}
+
+/// The whole analysed database, keyed by object name.
+#[derive(Serialize)]
+pub struct JsonDatabase<'a> {
+    pub objects: BTreeMap<&'a str, JsonObject<'a>>,
+}
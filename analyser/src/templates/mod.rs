@@ -2,7 +2,31 @@ use sailfish::Template;
 
 use crate::adb::{AdbEntryKind, AdbXref};
 
+pub mod json;
 pub mod nav;
+pub mod text;
+
+/// File format for decompiled objects.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Html,
+    #[value(alias = "txt")]
+    Text,
+    #[value(name = "md", alias = "markdown")]
+    Markdown,
+    Json,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Text => "txt",
+            Self::Markdown => "md",
+            Self::Json => "json",
+        }
+    }
+}
 
 #[derive(Template)]
 #[template(path = "../src/templates/bytecode.stpl")]
@@ -12,7 +36,9 @@ pub struct Bytecode<'a> {
     pub rendered_breadcrumbs: String,
     pub rendered_hierarchy: &'a str,
     pub code: crate::dis::DisCode<'a>,
-    pub pretty: Option<String>,
+    pub pretty: Option<crate::dis::DisRendered>,
+    /// Control-flow graph, as an SVG image.
+    pub cfg: Option<String>,
     pub xrefs: Vec<AdbXref>,
 }
 
@@ -0,0 +1,190 @@
+use crate::adb::AdbEntryKind;
+
+use super::Bytecode;
+
+/// Number of bytes shown per row of the hexdump column.
+const HEX_BYTES_PER_ROW: usize = 8;
+
+/// Widest the assembly and decompiled columns are padded to. Longer cells are
+/// not truncated, they just push the rest of the row to the right.
+const MAX_COLUMN_WIDTH: usize = 40;
+
+/// Removes markup from an HTML fragment produced by the disassembler, for the
+/// cells of the bytecode listing. Line breaks are kept, media tags are
+/// replaced by the path to their source, and entities are decoded.
+pub fn strip_markup(html: &str) -> String {
+    let mut ret = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        ret.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        let name = tag.split_whitespace().next().unwrap_or("").trim_end_matches('/');
+        match name {
+            "br" => ret.push('\n'),
+            "img" | "audio" | "video" => if let Some(src) = tag.split("src=\"").nth(1) {
+                ret.push_str(src.split('"').next().unwrap());
+            },
+            _ => (),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    ret.push_str(rest);
+    ret
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+impl Bytecode<'_> {
+    /// Renders the same sections as the HTML template (cross references,
+    /// event handlers, decompiled script, bytecode listing) without any markup. When
+    /// `markdown` is set, sections get headings and the script and listing
+    /// are put into code blocks.
+    pub fn render_text(&self, markdown: bool) -> String {
+        let mut ret = String::new();
+        let section = |ret: &mut String, title: &str| {
+            if markdown {
+                ret.push_str(&format!("\n## {title}\n\n"));
+            } else {
+                ret.push_str(&format!("\n{title}\n{}\n", "-".repeat(title.len())));
+            }
+        };
+        let fence = |ret: &mut String| if markdown {
+            ret.push_str("```\n");
+        };
+
+        if markdown {
+            ret.push_str(&format!("# {}\n", self.title));
+        } else {
+            ret.push_str(&format!("{}\n{}\n", self.title, "=".repeat(self.title.len())));
+        }
+
+        if !self.xrefs.is_empty() {
+            section(&mut ret, "Cross references");
+            let rows = self.xrefs.iter()
+                .map(|xref| [
+                    xref.other_key.clone(),
+                    xref.loc.map(|addr| format!("{addr:04x}")).unwrap_or_default(),
+                    format!("{:?}", xref.kind),
+                ])
+                .collect::<Vec<_>>();
+            if markdown {
+                ret.push_str("| object | offset | kind |\n");
+                ret.push_str("|--------|--------|------|\n");
+                for [object, offset, kind] in rows {
+                    ret.push_str(&format!("| {object} | {offset} | {kind} |\n"));
+                }
+            } else {
+                let width = rows.iter().map(|[object, ..]| object.len()).max().unwrap_or(0).max("object".len());
+                ret.push_str(&format!("{:width$}  offset  kind\n", "object"));
+                for [object, offset, kind] in rows {
+                    ret.push_str(&format!("{object:width$}  {offset:6}  {kind}\n"));
+                }
+            }
+        }
+
+        if !self.code.handlers.is_empty() {
+            section(&mut ret, "Event handlers");
+            for (idx, handler) in self.code.handlers.iter().enumerate() {
+                let mut header = format!(
+                    "{:04x}  {:04x}..{:04x}  {}",
+                    handler.addr, handler.body.start, handler.body.end, handler.trigger.text,
+                );
+                if let Some(condition) = &handler.condition {
+                    header.push_str(&format!(" if {}", condition.text));
+                }
+                if markdown {
+                    let sep = if idx == 0 { "" } else { "\n" };
+                    ret.push_str(&format!("{sep}### {}\n\n", header.trim_end()));
+                } else {
+                    ret.push_str(&format!("\n{}\n", header.trim_end()));
+                }
+                if let Some(script) = &handler.script {
+                    fence(&mut ret);
+                    for line in script.text.trim_end().lines() {
+                        ret.push_str(line.trim_end());
+                        ret.push('\n');
+                    }
+                    fence(&mut ret);
+                }
+            }
+        }
+
+        if let Some(pretty) = &self.pretty {
+            let pretty = &pretty.text;
+            if !pretty.trim().is_empty() {
+                if matches!(self.kind, AdbEntryKind::Code(..)) {
+                    section(&mut ret, "Decompiled script");
+                } else {
+                    section(&mut ret, "Decompiled");
+                }
+                fence(&mut ret);
+                for line in pretty.trim_end().lines() {
+                    ret.push_str(line.trim_end());
+                    ret.push('\n');
+                }
+                fence(&mut ret);
+            }
+        }
+
+        if !self.code.lines.is_empty() {
+            section(&mut ret, "Bytecode");
+            fence(&mut ret);
+            self.render_listing(&mut ret);
+            fence(&mut ret);
+        }
+        ret
+    }
+
+    /// Lays out the bytecode listing in aligned columns. Cells spanning
+    /// several lines (long hexdumps, multi-statement decompilation) continue
+    /// on the following rows with the offset column left blank.
+    fn render_listing(&self, ret: &mut String) {
+        let cells = |text: Option<&String>| text
+            .map(|s| strip_markup(s).lines().map(|l| l.trim_end().to_string()).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let lines = self.code.lines.iter()
+            .map(|line| {
+                let hex = strip_markup(&line.hex)
+                    .split_whitespace()
+                    .map(|b| b.to_string())
+                    .collect::<Vec<_>>()
+                    .chunks(HEX_BYTES_PER_ROW)
+                    .map(|row| row.join(" "))
+                    .collect::<Vec<_>>();
+                (line.span.start, [hex, cells(line.asm.as_ref()), cells(line.decomp.as_ref()), cells(line.comments.as_ref())])
+            })
+            .collect::<Vec<_>>();
+
+        let width = |col: usize, header: &str| lines.iter()
+            .flat_map(|(_, cols)| cols[col].iter())
+            .map(|cell| cell.chars().count())
+            .filter(|len| *len <= MAX_COLUMN_WIDTH)
+            .chain([header.len()])
+            .max()
+            .unwrap();
+        let hex_width = HEX_BYTES_PER_ROW * 3 - 1;
+        let asm_width = width(1, "assembly");
+        let dec_width = width(2, "decompiled");
+
+        let mut row = |addr: &str, hex: &str, asm: &str, dec: &str, com: &str| {
+            let row = format!("{addr:6}  {hex:hex_width$}  {asm:asm_width$}  {dec:dec_width$}  {com}");
+            ret.push_str(row.trim_end());
+            ret.push('\n');
+        };
+        row("offset", "hexdump", "assembly", "decompiled", "comments");
+        for (start, cols) in &lines {
+            let height = cols.iter().map(|c| c.len()).max().unwrap().max(1);
+            for idx in 0..height {
+                let addr = if idx == 0 { format!("{start:04x}") } else { String::new() };
+                let cell = |col: usize| cols[col].get(idx).map(|s| s.as_str()).unwrap_or("");
+                row(&addr, cell(0), cell(1), cell(2), cell(3));
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use crate::{
+    adb::{self, AdbEntry, AdbEntryKind},
+    dis::{self, code::{opdb::OpcodeDb, opmap::OpcodeMap}},
+    encoding::TextEncoding,
+    known,
+    patches::{self, Patch},
+    templates::nav::NavTree,
+    Resources,
+};
+
+/// Largest number of code objects decompiled with every opcode map.
+const SAMPLE_SIZE: usize = 64;
+
+/// Labels known objects of a version.
+type Labels = fn(&mut NavTree, &mut HashMap<String, AdbEntry>);
+
+/// Patches and labels of the built-in versions, by the name of their opcode
+/// map. Versions which are not listed have neither.
+const BUILTIN: &[(&str, &[&Patch<'static>], Option<Labels>)] = &[
+    ("1.0en", patches::ACTIVE_PATCHES, Some(known::apply_known)),
+];
+
+/// Everything that depends on the game version: the opcode map, the text
+/// encoding, the patches which apply to its data, and the labels of known
+/// objects. The profile is passed to everything which decodes or encodes
+/// objects, so that versions can be analysed side by side.
+pub struct VersionProfile {
+    pub name: String,
+    pub map: OpcodeMap,
+    /// Encoding given by the map file, or "windows-1250" if it gives none.
+    pub encoding: TextEncoding,
+    pub patches: &'static [&'static Patch<'static>],
+    pub labels: Option<Labels>,
+}
+
+impl VersionProfile {
+    /// Loads the profile of a built-in version, or of a map file, which has
+    /// no patches or labels.
+    pub fn load(id: &str) -> Result<Self, String> {
+        let map = OpcodeMap::load(id)?;
+        let (patches, labels) = BUILTIN.iter()
+            .find(|(name, ..)| *name == id)
+            .map(|(_, patches, labels)| (*patches, *labels))
+            .unwrap_or((&[], None));
+        let encoding = match map.encoding.as_deref() {
+            Some(label) => TextEncoding::for_label(label).ok_or_else(|| format!("no such encoding {label:?}"))?,
+            None => TextEncoding::default(),
+        };
+        Ok(Self {
+            name: id.to_string(),
+            encoding,
+            map,
+            patches,
+            labels,
+        })
+    }
+}
+
+/// Game version identified by `detect`.
+pub struct Detected {
+    pub name: &'static str,
+    /// Number of sampled objects which could not be decompiled, for every
+    /// built-in map.
+    pub errors: Vec<(&'static str, usize)>,
+    pub sample: usize,
+}
+
+/// Identifies the game version of a data.adb file by decompiling a sample of
+/// its code objects with every built-in map, and picking the map with the
+/// fewest errors. Ties go to the map listed first. The hashes of the released
+/// data.adb files are not known, so files are not matched by hash.
+pub fn detect(data: &[u8]) -> Result<Detected, String> {
+    let profiles = OpcodeMap::builtin_names()
+        .map(|name| VersionProfile::load(name).map(|profile| (name, profile)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Spread the sample over the whole file, as objects of one scene tend to
+    // be alike.
+    let entries = adb::extract(data.to_vec()).collect::<HashMap<_, _>>();
+    let mut keys = entries.iter()
+        .filter(|(_, entry)| matches!(entry.kind, AdbEntryKind::Code(_)))
+        .map(|(key, _)| key)
+        .collect::<Vec<_>>();
+    keys.sort();
+    let step = keys.len().div_ceil(SAMPLE_SIZE).max(1);
+    let sample = keys.into_iter().step_by(step).collect::<Vec<_>>();
+
+    let assets = HashMap::new();
+    let opcodes = OpcodeDb::default();
+    let mut errors = Vec::new();
+    for (name, profile) in &profiles {
+        let res = Resources {
+            entries: &entries,
+            data: &assets,
+            do_analyse: false,
+            do_cfg: false,
+            first_pass: true,
+            opcodes: &opcodes,
+            profile,
+        };
+        let count = sample.iter()
+            .filter(|key| {
+                let AdbEntryKind::Code(code) = &entries[**key].kind else { unreachable!() };
+                dis::analyse_code(code, res).map_or(true, |(_, output)| output.error)
+            })
+            .count();
+        errors.push((*name, count));
+    }
+    let (best, _) = errors.iter()
+        .enumerate()
+        .min_by_key(|(_, (_, count))| *count)
+        .ok_or("no built-in opcode maps")?;
+    let (name, _) = &profiles[best];
+    Ok(Detected {
+        name,
+        errors,
+        sample: sample.len(),
+    })
+}